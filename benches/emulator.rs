@@ -0,0 +1,85 @@
+// Emulator throughput, run with `cargo bench`. Each benchmark runs a program to completion several times and reports
+// the best time per run and the instructions executed per second.
+
+use std::time::{Duration, Instant};
+
+use lmc::machine::io::{Input, Output};
+use lmc::Machine;
+
+const RUNS: u32 = 10;
+
+// Counts down from 65535 to 0, two nested loops of this
+const COUNTDOWN: &str = "
+outer   lda MAX
+inner   sub ONE
+        brz next
+        bra inner
+next    lda TIMES
+        sub ONE
+        sta TIMES
+        brz end
+        bra outer
+end     hlt
+MAX     dat 65535
+ONE     dat 1
+TIMES   dat 20
+";
+
+// Many calls, multiplication by repeated addition in a function
+const CALLS: &str = "
+use std;
+
+fn multiply(a, b) {
+    let result = 0;
+    for let i = 0; i < b; i = i + 1; {
+        result = result + a;
+    }
+
+    return result;
+}
+
+fn main() {
+    let total = 0;
+    for let n = 0; n < 2000; n = n + 1; {
+        total = multiply(3, 100);
+    }
+
+    println(total);
+    return 0;
+}
+";
+
+fn bench(name: &str, program: &[u8], input: &str) {
+    let mut best = Duration::MAX;
+    let mut cycles = 0;
+
+    for _ in 0..RUNS {
+        let mut m = Machine::builder()
+            .input(Input::lines(input))
+            .output(Output::BUFFER(String::new()))
+            .load(program.to_vec())
+            .unwrap();
+
+        let start = Instant::now();
+        m.emulate().unwrap();
+        best = best.min(start.elapsed());
+        cycles = m.cycles();
+    }
+
+    let per_second = cycles as f64 / best.as_secs_f64();
+    println!("{:<12} {:>10} cycles {:>10.3} ms {:>8.1} M cycles/s", name, cycles, best.as_secs_f64() * 1000.0, per_second / 1e6);
+}
+
+fn main() {
+    let countdown = lmc::assemble(COUNTDOWN).unwrap();
+    bench("countdown", &countdown.binary, "");
+
+    let calls = lmc::assemble(&lmc::compile(CALLS).unwrap()).unwrap();
+    bench("calls", &calls.binary, "");
+
+    let fizzbuzz = lmc::assemble(&lmc::compile(include_str!("../examples/lmc/fizzbuzz.lmc")).unwrap()).unwrap();
+    bench("fizzbuzz", &fizzbuzz.binary, "");
+
+    let calculator = lmc::assemble(&lmc::compile(include_str!("../examples/lmc/calculator.lmc")).unwrap()).unwrap();
+    bench("calculator", &calculator.binary, "60000\n/\n3\n");
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use crate::assembler::lexer;
 use crate::assembler::parser;
+use crate::error::{Error, Result};
 
 pub struct Compiler {
     program: Vec<parser::Instruction>,
@@ -12,17 +13,17 @@ impl Compiler {
         Compiler { program: program, symbol_table: symbol_table } 
     }
 
-    pub fn compile(&mut self) -> Vec<u8>{
+    pub fn compile(&mut self) -> Result<Vec<u8>> {
         let mut out: Vec<u8> = vec![];
         for instruction in &self.program {
-            let bin_instruction = self.compile_instruction(instruction.clone());
+            let bin_instruction = self.compile_instruction(instruction.clone())?;
             out.extend(bin_instruction);
         }
 
-        out
+        Ok(out)
     }
 
-    fn compile_instruction(&self, instruction: parser::Instruction) -> Vec<u8> {
+    pub fn compile_instruction(&self, instruction: parser::Instruction) -> Result<Vec<u8>> {
         let opcode_map: fn(&parser::Instruction) -> u8 = |instruction: &parser::Instruction| match instruction {
             parser::Instruction::HLT    => 0b0000,
             parser::Instruction::ADD(_) => 0b0001,
@@ -39,39 +40,33 @@ impl Compiler {
             parser::Instruction::DAT(_) => 0b1100,
             parser::Instruction::CALL(_) => 0b1101,
             parser::Instruction::RET => 0b1110,
+            parser::Instruction::INC => 0b1111,
         };
 
-        let operand_map = |instruction: &parser::Instruction| match instruction {
-            parser::Instruction::HLT | parser::Instruction::INP | 
-            parser::Instruction::OUT | parser::Instruction::OTC |
-            parser::Instruction::RET => 0,
-
-            parser::Instruction::ADD(operand) | parser::Instruction::SUB(operand) | 
-            parser::Instruction::LDA(operand) | parser::Instruction::STA(operand) | 
-            parser::Instruction::BRA(operand) | parser::Instruction::BRZ(operand) | 
-            parser::Instruction::BGT(operand) | parser::Instruction::BLT(operand) |
-            parser::Instruction::DAT(operand) | parser::Instruction::CALL(operand) 
-            => self.compile_operand(operand.clone()),
+        let operand_map = |instruction: &parser::Instruction| match instruction.operand() {
+            None => Ok(0),
+            Some(operand) => self.compile_operand(operand.clone()),
         };
 
 
         let bin_opcode = opcode_map(&instruction);
-        let bin_operand = operand_map(&instruction);
+        let bin_operand = operand_map(&instruction)?;
 
-        vec![bin_opcode, (bin_operand >> 8) as u8, bin_operand as u8]
+        Ok(vec![bin_opcode, (bin_operand >> 8) as u8, bin_operand as u8])
     }
 
-    fn compile_operand(&self, operand: lexer::Token) -> u16 {
-        // Label: replace with addr*3 (3 byte instructions) e.g. 0=0, 1=3, 6=18,
+    fn compile_operand(&self, operand: lexer::Token) -> Result<u16> {
+        // Label: replace with the address of the cell it labels (see machine::memory)
         match operand {
-            lexer::Token::Number(value) => { return value }
+            lexer::Token::Number(value) => { return Ok(value) }
             lexer::Token::Label(identifier) => {
-                // Lookup, *3, u16
-                let address = self.symbol_table.get(&identifier).expect(&format!("undefined label found in compiler, got: {}", identifier));
-                return address*3;
+                match self.symbol_table.get(&identifier) {
+                    Some(address) => { return Ok(*address) }
+                    None => { return Err(Error::Assembler(format!("undefined label found in compiler, got: {}", identifier))) }
+                }
             }
 
-            _ => { panic!("unexpected operand found in compile_operand(): {:?}", operand)}
+            _ => { return Err(Error::Assembler(format!("unexpected operand found in compile_operand(): {:?}", operand))) }
         }
     }
 }
@@ -91,13 +86,12 @@ mod tests {
         ]));
 
         let bin = c.compile();
-        assert_eq!(bin, vec![
-            3, 0, 3, 12, 0, 1
-        ])
+        assert_eq!(bin, Ok(vec![
+            3, 0, 1, 12, 0, 1
+        ]))
     }
 
     #[test]
-    #[should_panic]
     fn test_undefined_label() {
         let mut c = Compiler::new(vec![
             parser::Instruction::LDA(lexer::Token::Label(String::from("ONE"))),
@@ -105,13 +99,13 @@ mod tests {
         ], 
         HashMap::new());
 
-        c.compile();
+        assert_eq!(c.compile(), Err(Error::Assembler(String::from("undefined label found in compiler, got: ONE"))));
     }
 
     #[test]
     fn test_empty() {
         let mut c = Compiler::new(vec![], HashMap::new());
         let bin = c.compile();
-        assert_eq!(bin, vec![])
+        assert_eq!(bin, Ok(vec![]))
     }
 }
\ No newline at end of file
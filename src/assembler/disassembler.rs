@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use crate::machine::memory::Cell;
+
+// An instruction as assembly, with labels for the addresses it refers to, e.g. lda ONE. Addresses without a label,
+// or every address when symbols is empty, are written as numbers.
+pub fn disassemble(cell: Cell, symbols: &HashMap<String, u16>) -> String {
+    match (mnemonic(cell.opcode), takes_address(cell.opcode)) {
+        (Some(mnemonic), true) => { format!("{} {}", mnemonic, label(cell.value, symbols).unwrap_or(cell.value.to_string())) }
+        (Some("dat"), false) => { format!("dat {}", cell.value) }
+        (Some(mnemonic), false) => { mnemonic.to_string() }
+        (None, _) => { format!("?? {:#06b} {}", cell.opcode, cell.value) }
+    }
+}
+
+// The label of an address, the first in order when it has several
+pub fn label(address: u16, symbols: &HashMap<String, u16>) -> Option<String> {
+    let mut labels: Vec<&String> = symbols.iter().filter(|(_, a)| **a == address).map(|(label, _)| label).collect();
+    labels.sort();
+    labels.first().map(|label| label.to_string())
+}
+
+// Assembly mnemonic of an opcode, the inverse of the assembler's opcode map
+pub fn mnemonic(opcode: u8) -> Option<&'static str> {
+    let mnemonic = match opcode {
+        0b0000 => { "hlt" }
+        0b0001 => { "add" }
+        0b0010 => { "sub" }
+        0b0011 => { "lda" }
+        0b0100 => { "sta" }
+        0b0101 => { "bra" }
+        0b0110 => { "brz" }
+        0b0111 => { "bgt" }
+        0b1000 => { "inp" }
+        0b1001 => { "out" }
+        0b1010 => { "otc" }
+        0b1011 => { "blt" }
+        0b1100 => { "dat" }
+        0b1101 => { "call" }
+        0b1110 => { "ret" }
+        0b1111 => { "inc" }
+        _ => { return None; }
+    };
+
+    Some(mnemonic)
+}
+
+// Whether the operand of an opcode is an address, rather than unused or a value
+pub fn takes_address(opcode: u8) -> bool {
+    matches!(opcode, 0b0001..=0b0111 | 0b1011 | 0b1101)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        let program = crate::assemble("start lda ONE\nbrz start\nout\nONE dat 1").unwrap();
+        let cells: Vec<Cell> = program.binary.chunks(3).map(|c| Cell { opcode: c[0], value: u16::from_be_bytes([c[1], c[2]]) }).collect();
+
+        let disassembled: Vec<String> = cells.iter().map(|cell| disassemble(*cell, &program.symbol_table)).collect();
+        assert_eq!(disassembled, vec!["lda ONE", "brz start", "out", "dat 1"]);
+
+        let disassembled: Vec<String> = cells.iter().map(|cell| disassemble(*cell, &HashMap::new())).collect();
+        assert_eq!(disassembled, vec!["lda 3", "brz 0", "out", "dat 1"]);
+    }
+}
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use crate::error::{Error, Result};
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum Token {
@@ -19,9 +20,13 @@ pub enum Token {
     INP,
     OUT,
     OTC,
+    INC,
     DAT,
     CALL,
     RET,
+
+    MACRO,
+    ENDM,
 }
 
 
@@ -42,11 +47,11 @@ impl Lexer {
     }
 
 
-    pub fn lex(&mut self) -> Vec<Token> {
+    pub fn lex(&mut self) -> Result<Vec<Token>> {
         let mut tokens: Vec<Token> = vec![];
         self.read_char();
         loop {
-            let token = self.next_token();
+            let token = self.next_token()?;
             if token == Token::EOF {
                 tokens.push(token);
                 break;
@@ -56,7 +61,7 @@ impl Lexer {
 
         }
 
-        return tokens;
+        return Ok(tokens);
     }
 
 
@@ -72,20 +77,20 @@ impl Lexer {
     }
 
 
-    pub fn next_token(&mut self) -> Token {
+    pub fn next_token(&mut self) -> Result<Token> {
         self.eat_whitespace();
 
         let tok: Token;
         match self.ch {
             '0'..='9' => { return self.read_number() }
-            '_' |'A'..='z' => { return self.read_identifier() }
+            '_' |'A'..='z' => { return Ok(self.read_identifier()) }
             '\n' => { tok = Token::NEWLINE }
             '\0' => { tok = Token::EOF }
-            _ => { panic!("Unexpected character found in lexer: {}", self.ch) }
+            _ => { return Err(Error::Lexer(format!("Unexpected character found in lexer: {}", self.ch))) }
         }
 
         self.read_char();
-        return tok;
+        return Ok(tok);
     }
 
 
@@ -96,14 +101,17 @@ impl Lexer {
     }
 
 
-    pub fn read_number(&mut self) -> Token {
+    pub fn read_number(&mut self) -> Result<Token> {
         let position = self.position;
         while self.position < self.input.len() && self.ch.is_numeric() {
             self.read_char();
         }
 
         let numeric_identifier: String = self.input[position..self.position].to_vec().iter().collect();
-        return Token::Number(numeric_identifier.parse::<u16>().unwrap());
+        match numeric_identifier.parse::<u16>() {
+            Ok(value) => { return Ok(Token::Number(value)) }
+            Err(_) => { return Err(Error::Lexer(format!("number does not fit in a 16 bit word: {}", numeric_identifier))) }
+        }
     }
 
 
@@ -122,9 +130,12 @@ impl Lexer {
             ("inp", Token::INP),
             ("out", Token::OUT),
             ("otc", Token::OTC),
+            ("inc", Token::INC),
             ("dat", Token::DAT),
             ("call", Token::CALL),
             ("ret", Token::RET),
+            ("macro", Token::MACRO),
+            ("endm", Token::ENDM),
             ].iter().cloned().collect();
 
         let position = self.position;
@@ -151,7 +162,7 @@ mod tests {
     #[test]
     fn test_lex() {
         let mut l = Lexer::new(String::from("main add 0\n\tsta RESULT").chars().collect());
-        assert_eq!(l.lex(), vec![
+        assert_eq!(l.lex().unwrap(), vec![
             Token::Label(String::from("main")),
             Token::ADD,
             Token::Number(0),
@@ -165,7 +176,7 @@ mod tests {
     #[test]
     fn test_keyword_capitalisation() {
         let mut l = Lexer::new(String::from("ADD sta DaT\n lda lad").chars().collect());
-        assert_eq!(l.lex(), vec![
+        assert_eq!(l.lex().unwrap(), vec![
             Token::ADD,
             Token::STA,
             Token::DAT,
@@ -179,7 +190,7 @@ mod tests {
     #[test]
     fn test_identifier_underscores() {
         let mut l = Lexer::new(String::from("_start st_art start_ _").chars().collect());
-        assert_eq!(l.lex(), vec![
+        assert_eq!(l.lex().unwrap(), vec![
             Token::Label(String::from("_start")),
             Token::Label(String::from("st_art")),
             Token::Label(String::from("start_")),
@@ -192,13 +203,13 @@ mod tests {
     #[test]
     fn test_empty() {
         let mut l = Lexer::new(String::from("").chars().collect());
-        assert_eq!(l.lex(), vec![ Token::EOF ])
+        assert_eq!(l.lex().unwrap(), vec![ Token::EOF ])
     }
 
     #[test]
     fn test_whitespace() {
         let mut l = Lexer::new(String::from("\t\n\t    \t\t \r\n").chars().collect());
-        assert_eq!(l.lex(), vec![ 
+        assert_eq!(l.lex().unwrap(), vec![ 
             Token::NEWLINE,
             Token::NEWLINE,
             Token::EOF 
@@ -206,9 +217,14 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
-    fn test_panic() {
+    fn test_unexpected_character() {
         let mut l = Lexer::new(String::from("add #10").chars().collect());
-        l.lex();
+        assert_eq!(l.lex(), Err(Error::Lexer(String::from("Unexpected character found in lexer: #"))));
+    }
+
+    #[test]
+    fn test_number_overflow() {
+        let mut l = Lexer::new(String::from("dat 65536").chars().collect());
+        assert!(l.lex().is_err());
     }
 }
\ No newline at end of file
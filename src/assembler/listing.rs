@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use crate::assembler::assembler;
+use crate::assembler::lexer;
+use crate::assembler::parser;
+use crate::error::Result;
+
+// Renders an assembly listing, each source line alongside the address, encoding and resolved operand of the
+// instructions it produced, followed by the symbol table with the lines each symbol is referenced from
+pub struct Listing<'a> {
+    program: &'a [parser::Instruction],
+    symbol_table: &'a HashMap<String, u16>,
+    lines: &'a [usize],
+}
+
+impl<'a> Listing<'a> {
+    pub fn new(program: &'a [parser::Instruction], symbol_table: &'a HashMap<String, u16>, lines: &'a [usize]) -> Self {
+        Listing { program: program, symbol_table: symbol_table, lines: lines }
+    }
+
+    pub fn render(&self, source: &str) -> Result<String> {
+        let c = assembler::Compiler::new(self.program.to_vec(), self.symbol_table.clone());
+
+        let mut instructions: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (instruction_number, line) in self.lines.iter().enumerate() {
+            instructions.entry(*line).or_default().push(instruction_number);
+        }
+
+        let mut out = format!("{:>5}  {:<6} {:<8}  {:<16}  {}\n", "line", "addr", "bytes", "operand", "source");
+        for (index, source_line) in source.lines().enumerate() {
+            let line = index + 1;
+            let numbers = instructions.get(&line).cloned().unwrap_or_default();
+            if numbers.is_empty() {
+                out += &row(format!("{:>5}  {:<6} {:<8}  {:<16}  {}", line, "", "", "", source_line));
+            }
+
+            for (i, instruction_number) in numbers.iter().enumerate() {
+                let instruction = &self.program[*instruction_number];
+                let bytes = c.compile_instruction(instruction.clone())?;
+                let encoded = format!("{:02x} {:02x} {:02x}", bytes[0], bytes[1], bytes[2]);
+                let source_text = if i == 0 { source_line } else { "" };
+
+                out += &row(format!("{:>5}  {:<6} {:<8}  {:<16}  {}",
+                    line, format!("{:04x}", instruction_number), encoded, self.render_operand(instruction, &bytes), source_text));
+            }
+        }
+
+        Ok(out + "\n" + &self.render_symbols())
+    }
+
+    fn render_operand(&self, instruction: &parser::Instruction, bytes: &[u8]) -> String {
+        let value = ((bytes[1] as u16) << 8) | bytes[2] as u16;
+        match instruction.operand() {
+            Some(lexer::Token::Label(identifier)) => { format!("{:04x} ({})", value, identifier) }
+            Some(_) => { format!("{:04x}", value) }
+            None => { String::new() }
+        }
+    }
+
+    fn render_symbols(&self) -> String {
+        let mut references: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (instruction, line) in self.program.iter().zip(self.lines) {
+            if let Some(lexer::Token::Label(identifier)) = instruction.operand() {
+                let lines = references.entry(identifier.as_str()).or_default();
+                if !lines.contains(line) {
+                    lines.push(*line);
+                }
+            }
+        }
+
+        let mut symbols: Vec<(&String, &u16)> = self.symbol_table.iter().collect();
+        symbols.sort();
+
+        let mut out = format!("{:<16}  {:<6} {:>7}  {}\n", "symbol", "addr", "defined", "references");
+        for (symbol, instruction_number) in symbols {
+            let defined = match self.lines.get(*instruction_number as usize) {
+                Some(line) => { line.to_string() }
+                None => { String::from("-") }
+            };
+
+            let referenced: Vec<String> = references.get(symbol.as_str()).cloned().unwrap_or_default()
+                .iter().map(|line| line.to_string()).collect();
+
+            out += &row(format!("{:<16}  {:<6} {:>7}  {}", symbol, format!("{:04x}", instruction_number), defined, referenced.join(", ")));
+        }
+
+        out
+    }
+}
+
+fn row(line: String) -> String {
+    line.trim_end().to_string() + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(source: &str) -> String {
+        let mut l = lexer::Lexer::new(source.chars().collect());
+        let mut p = parser::Parser::new(l.lex().unwrap());
+        let (program, symbol_table) = p.parse().unwrap();
+        Listing::new(&program, &symbol_table, &p.lines).render(source).unwrap()
+    }
+
+    #[test]
+    fn test_listing_instructions() {
+        let out = listing("lda ONE\nout\n\nONE dat 1");
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[1], "    1  0000   03 00 02  0002 (ONE)        lda ONE");
+        assert_eq!(lines[2], "    2  0001   09 00 00                    out");
+        assert_eq!(lines[3], "    3");
+        assert_eq!(lines[4], "    4  0002   0c 00 01  0001              ONE dat 1");
+    }
+
+    #[test]
+    fn test_listing_symbols() {
+        let out = listing("loop lda ONE\nbra loop\nONE dat 1\nEND");
+        let symbols: Vec<&str> = out.split("\n\n").nth(1).unwrap().lines().collect();
+        assert_eq!(symbols[1], "END               0003         -");
+        assert_eq!(symbols[2], "ONE               0002         3  1");
+        assert_eq!(symbols[3], "loop              0000         1  2");
+    }
+}
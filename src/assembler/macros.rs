@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use crate::assembler::lexer;
+use crate::error::{Error, Result};
+
+// Expansions may invoke other macros, this bounds runaway (recursive) definitions
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Macro {
+    params: Vec<String>,
+    body: Vec<Vec<lexer::Token>>,
+}
+
+// Expands `macro name args ... endm` definitions in a token stream before it reaches the parser, e.g.
+//
+//     macro copy from to
+//             lda from
+//             sta to
+//     endm
+//
+//             copy A B
+//
+// Every label defined inside a macro body is local to a single expansion, so is renamed to _<macro>_<n>_<label>
+pub struct Expander {
+    tokens: Vec<lexer::Token>,
+    macros: HashMap<String, Macro>,
+    expansions: usize,
+    pub lines: Vec<usize>, // source line of each expanded token, expansions map to the line of their invocation
+}
+
+impl Expander {
+    pub fn new(tokens: Vec<lexer::Token>) -> Self {
+        Expander { tokens: tokens, macros: HashMap::new(), expansions: 0, lines: vec![] }
+    }
+
+    pub fn expand(&mut self) -> Result<Vec<lexer::Token>> {
+        let lines = self.split_lines();
+        let mut out: Vec<(usize, Vec<lexer::Token>)> = vec![];
+
+        let mut position = 0;
+        while position < lines.len() {
+            let line = &lines[position];
+            if line.first() == Some(&lexer::Token::MACRO) {
+                position = self.define_macro(&lines, position)?;
+                continue;
+            }
+
+            if line.first() == Some(&lexer::Token::ENDM) {
+                return Err(Error::Macro(format!("endm found outside of a macro definition, line: {}", position + 1)));
+            }
+
+            for expanded in self.expand_line(line.clone(), 0)? {
+                out.push((position + 1, expanded));
+            }
+            position += 1;
+        }
+
+        let mut tokens: Vec<lexer::Token> = vec![];
+        self.lines = vec![];
+        for (line_number, line) in out {
+            self.lines.extend(vec![line_number; line.len() + 1]);
+            tokens.extend(line);
+            tokens.push(lexer::Token::NEWLINE);
+        }
+
+        self.lines.push(lines.len());
+        tokens.push(lexer::Token::EOF);
+        Ok(tokens)
+    }
+
+    fn split_lines(&self) -> Vec<Vec<lexer::Token>> {
+        let mut lines: Vec<Vec<lexer::Token>> = vec![vec![]];
+        for token in &self.tokens {
+            match token {
+                lexer::Token::NEWLINE => { lines.push(vec![]); }
+                lexer::Token::EOF => { break; }
+                _ => { lines.last_mut().unwrap().push(token.clone()); }
+            }
+        }
+
+        lines
+    }
+
+    // Consumes the definition starting at lines[start], returns the position of the line following `endm`
+    fn define_macro(&mut self, lines: &[Vec<lexer::Token>], start: usize) -> Result<usize> {
+        let header = &lines[start];
+        let name = match header.get(1) {
+            Some(lexer::Token::Label(name)) => { name.clone() }
+            _ => { return Err(Error::Macro(format!("expected macro name following macro, line: {}", start + 1))) }
+        };
+
+        let mut params: Vec<String> = vec![];
+        for token in &header[2..] {
+            match token {
+                lexer::Token::Label(param) => { params.push(param.clone()); }
+                _ => { return Err(Error::Macro(format!("expected parameter name in macro {}, got: {:?}", name, token))) }
+            }
+        }
+
+        let mut body: Vec<Vec<lexer::Token>> = vec![];
+        let mut position = start + 1;
+        loop {
+            if position >= lines.len() {
+                return Err(Error::Macro(format!("unterminated macro: {}, expected endm", name)));
+            }
+
+            match lines[position].first() {
+                Some(lexer::Token::ENDM) => { break; }
+                Some(lexer::Token::MACRO) => { return Err(Error::Macro(format!("nested macro definition in macro: {}, line: {}", name, position + 1))) }
+                _ => { body.push(lines[position].clone()); }
+            }
+
+            position += 1;
+        }
+
+        self.macros.insert(name, Macro { params: params, body: body });
+        Ok(position + 1)
+    }
+
+    fn expand_line(&mut self, line: Vec<lexer::Token>, depth: usize) -> Result<Vec<Vec<lexer::Token>>> {
+        match line.as_slice() {
+            [lexer::Token::Label(name), args @ ..] if self.macros.contains_key(name) => {
+                self.expand_invocation(name.clone(), args.to_vec(), depth)
+            }
+
+            // Label preceding an invocation marks the first instruction of the expansion
+            [label @ lexer::Token::Label(_), lexer::Token::Label(name), args @ ..] if self.macros.contains_key(name) => {
+                let mut lines = vec![vec![label.clone()]];
+                lines.extend(self.expand_invocation(name.clone(), args.to_vec(), depth)?);
+                Ok(lines)
+            }
+
+            _ => { Ok(vec![line]) }
+        }
+    }
+
+    fn expand_invocation(&mut self, name: String, args: Vec<lexer::Token>, depth: usize) -> Result<Vec<Vec<lexer::Token>>> {
+        if depth >= MAX_DEPTH {
+            return Err(Error::Macro(format!("macro expansion exceeded maximum depth of {} in macro: {}", MAX_DEPTH, name)));
+        }
+
+        let definition = self.macros.get(&name).unwrap().clone();
+        if args.len() != definition.params.len() {
+            return Err(Error::Macro(format!("macro {} expects {} arguments, got: {}", name, definition.params.len(), args.len())));
+        }
+
+        let mut substitutions: HashMap<String, lexer::Token> = HashMap::new();
+        for (param, arg) in definition.params.iter().zip(args) {
+            match arg {
+                lexer::Token::Label(_) | lexer::Token::Number(_) => { substitutions.insert(param.clone(), arg); }
+                _ => { return Err(Error::Macro(format!("unexpected argument to macro {}, got: {:?}", name, arg))) }
+            }
+        }
+
+        self.expansions += 1;
+        for line in &definition.body {
+            if let Some(lexer::Token::Label(label)) = line.first() {
+                if !substitutions.contains_key(label) && !self.macros.contains_key(label) {
+                    let local = lexer::Token::Label(format!("_{}_{}_{}", name, self.expansions, label));
+                    substitutions.insert(label.clone(), local);
+                }
+            }
+        }
+
+        let mut lines: Vec<Vec<lexer::Token>> = vec![];
+        for line in definition.body {
+            let substituted: Vec<lexer::Token> = line.into_iter().map(|token| match &token {
+                lexer::Token::Label(identifier) => { substitutions.get(identifier).cloned().unwrap_or(token) }
+                _ => { token }
+            }).collect();
+
+            lines.extend(self.expand_line(substituted, depth + 1)?);
+        }
+
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(program: &str) -> Result<Vec<lexer::Token>> {
+        let mut l = lexer::Lexer::new(program.chars().collect());
+        Expander::new(l.lex()?).expand()
+    }
+
+    #[test]
+    fn test_expand_parameters() {
+        assert_eq!(expand("macro copy from to\nlda from\nsta to\nendm\ncopy A 10"), Ok(vec![
+            lexer::Token::LDA, lexer::Token::Label(String::from("A")), lexer::Token::NEWLINE,
+            lexer::Token::STA, lexer::Token::Number(10), lexer::Token::NEWLINE,
+            lexer::Token::EOF,
+        ]))
+    }
+
+    #[test]
+    fn test_local_labels() {
+        let tokens = expand("macro spin\nloop bra loop\nendm\nspin\nspin").unwrap();
+        assert_eq!(tokens, vec![
+            lexer::Token::Label(String::from("_spin_1_loop")), lexer::Token::BRA, lexer::Token::Label(String::from("_spin_1_loop")), lexer::Token::NEWLINE,
+            lexer::Token::Label(String::from("_spin_2_loop")), lexer::Token::BRA, lexer::Token::Label(String::from("_spin_2_loop")), lexer::Token::NEWLINE,
+            lexer::Token::EOF,
+        ])
+    }
+
+    #[test]
+    fn test_labelled_invocation() {
+        assert_eq!(expand("macro newline\nlda NL\notc\nendm\nstart newline"), Ok(vec![
+            lexer::Token::Label(String::from("start")), lexer::Token::NEWLINE,
+            lexer::Token::LDA, lexer::Token::Label(String::from("NL")), lexer::Token::NEWLINE,
+            lexer::Token::OTC, lexer::Token::NEWLINE,
+            lexer::Token::EOF,
+        ]))
+    }
+
+    #[test]
+    fn test_nested_invocation() {
+        let tokens = expand("macro show x\nlda x\nout\nendm\nmacro show_twice x\nshow x\nshow x\nendm\nshow_twice A").unwrap();
+        assert_eq!(tokens.iter().filter(|t| **t == lexer::Token::OUT).count(), 2);
+        assert_eq!(tokens.iter().filter(|t| **t == lexer::Token::Label(String::from("A"))).count(), 2);
+    }
+
+    #[test]
+    fn test_expansion_lines() {
+        let mut l = lexer::Lexer::new(String::from("macro twice\nout\nout\nendm\nhlt\ntwice").chars().collect());
+        let mut e = Expander::new(l.lex().unwrap());
+        let tokens = e.expand().unwrap();
+        assert_eq!(tokens.len(), e.lines.len());
+        assert_eq!(e.lines, vec![5, 5, 6, 6, 6, 6, 6]);
+    }
+
+    #[test]
+    fn test_argument_count() {
+        assert_eq!(expand("macro copy from to\nlda from\nsta to\nendm\ncopy A"), Err(Error::Macro(String::from("macro copy expects 2 arguments, got: 1"))));
+    }
+
+    #[test]
+    fn test_recursive_macro() {
+        assert!(expand("macro forever\nforever\nendm\nforever").is_err());
+    }
+
+    #[test]
+    fn test_unterminated_macro() {
+        assert!(expand("macro copy from to\nlda from\nsta to\n").is_err());
+    }
+}
@@ -1,3 +1,8 @@
 pub mod assembler;
+pub mod disassembler;
 pub mod lexer;
-pub mod parser;
\ No newline at end of file
+pub mod listing;
+pub mod macros;
+pub mod object;
+pub mod parser;
+pub mod program;
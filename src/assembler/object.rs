@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use crate::error::{Error, Result};
+use crate::reader::Reader;
+
+// Object file format
+//
+// An object file is an assembled binary image together with its symbol table and the source line of every
+// instruction, so tools can map addresses back to labels and lines. All integers are big-endian.
+//
+//     magic "LMCO", version u8
+//     binary length u32, binary image
+//     symbol count u16, per symbol: name length u16, name (UTF-8), address u16 (sorted by name)
+//     line count u32, per instruction: source line u32
+
+const MAGIC: &[u8] = b"LMCO";
+const VERSION: u8 = 1;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Object {
+    pub binary: Vec<u8>,
+    pub symbol_table: HashMap<String, u16>,
+    pub lines: Vec<usize>,
+}
+
+impl Object {
+    pub fn new(binary: Vec<u8>, symbol_table: HashMap<String, u16>, lines: Vec<usize>) -> Self {
+        Object { binary: binary, symbol_table: symbol_table, lines: lines }
+    }
+
+    pub fn is_object(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out: Vec<u8> = MAGIC.to_vec();
+        out.push(VERSION);
+
+        out.extend((self.binary.len() as u32).to_be_bytes());
+        out.extend(&self.binary);
+
+        let mut symbols: Vec<(&String, &u16)> = self.symbol_table.iter().collect();
+        symbols.sort();
+        out.extend((symbols.len() as u16).to_be_bytes());
+        for (name, address) in symbols {
+            out.extend((name.len() as u16).to_be_bytes());
+            out.extend(name.as_bytes());
+            out.extend(address.to_be_bytes());
+        }
+
+        out.extend((self.lines.len() as u32).to_be_bytes());
+        for line in &self.lines {
+            out.extend((*line as u32).to_be_bytes());
+        }
+
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Object> {
+        let mut r = Reader::new(bytes, "object", Error::Object);
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err(Error::Object(String::from("not an object file, missing LMCO header")));
+        }
+
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(Error::Object(format!("unsupported object version, got: {}", version)));
+        }
+
+        let length = r.u32()? as usize;
+        let binary = r.take(length)?.to_vec();
+
+        let mut symbol_table: HashMap<String, u16> = HashMap::new();
+        for _ in 0..r.u16()? {
+            let length = r.u16()? as usize;
+            let name = String::from_utf8(r.take(length)?.to_vec())
+                .map_err(|_| Error::Object(String::from("symbol name is not valid UTF-8")))?;
+            symbol_table.insert(name, r.u16()?);
+        }
+
+        let mut lines: Vec<usize> = vec![];
+        for _ in 0..r.u32()? {
+            lines.push(r.u32()? as usize);
+        }
+
+        r.end()?;
+        Ok(Object::new(binary, symbol_table, lines))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object() -> Object {
+        let symbol_table = HashMap::from([(String::from("ONE"), 2), (String::from("loop"), 0)]);
+        Object::new(vec![3, 0, 2, 5, 0, 0, 12, 0, 1], symbol_table, vec![1, 2, 4])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = object().encode();
+        assert!(Object::is_object(&bytes));
+        assert_eq!(&bytes[..5], b"LMCO\x01");
+        assert_eq!(Object::decode(&bytes), Ok(object()));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(Object::decode(&[3, 0, 2]), Err(Error::Object(String::from("unexpected end of object, at: 0"))));
+        assert_eq!(Object::decode(b"LMCX\x01"), Err(Error::Object(String::from("not an object file, missing LMCO header"))));
+
+        let bytes = object().encode();
+        assert!(Object::decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -1,5 +1,7 @@
 use std::collections::HashMap;
+use std::fmt;
 use crate::assembler::lexer;
+use crate::error::{Error, Result};
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum Instruction {
@@ -15,22 +17,81 @@ pub enum Instruction {
     INP,
     OUT,
     OTC,
+    INC,
     DAT(lexer::Token),
     CALL(lexer::Token),
     RET,
 }
 
+impl Instruction {
+    pub fn operand(&self) -> Option<&lexer::Token> {
+        match self {
+            Instruction::HLT | Instruction::INP | Instruction::OUT | 
+            Instruction::OTC | Instruction::INC | Instruction::RET => None,
+
+            Instruction::ADD(operand) | Instruction::SUB(operand) | 
+            Instruction::LDA(operand) | Instruction::STA(operand) | 
+            Instruction::BRA(operand) | Instruction::BRZ(operand) | 
+            Instruction::BGT(operand) | Instruction::BLT(operand) |
+            Instruction::DAT(operand) | Instruction::CALL(operand) => Some(operand),
+        }
+    }
+}
+
+// Assembly source of an instruction, e.g. lda ONE
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mnemonic = match self {
+            Instruction::HLT => { "hlt" }
+            Instruction::ADD(_) => { "add" }
+            Instruction::SUB(_) => { "sub" }
+            Instruction::LDA(_) => { "lda" }
+            Instruction::STA(_) => { "sta" }
+            Instruction::BRA(_) => { "bra" }
+            Instruction::BRZ(_) => { "brz" }
+            Instruction::BGT(_) => { "bgt" }
+            Instruction::BLT(_) => { "blt" }
+            Instruction::INP => { "inp" }
+            Instruction::OUT => { "out" }
+            Instruction::OTC => { "otc" }
+            Instruction::INC => { "inc" }
+            Instruction::DAT(_) => { "dat" }
+            Instruction::CALL(_) => { "call" }
+            Instruction::RET => { "ret" }
+        };
+
+        match self.operand() {
+            Some(lexer::Token::Label(identifier)) => { write!(f, "{} {}", mnemonic, identifier) }
+            Some(lexer::Token::Number(value)) => { write!(f, "{} {}", mnemonic, value) }
+            _ => { write!(f, "{}", mnemonic) }
+        }
+    }
+}
+
 pub struct Parser {
     tokens: Vec<lexer::Token>, 
+    line_numbers: Vec<usize>, // source line of each token
     position: usize,
     instruction_number: usize,
     tok: lexer::Token,
+    pub lines: Vec<usize>, // source line of each parsed instruction
 }
 
 impl Parser {
     pub fn new(tokens: Vec<lexer::Token>) -> Self {
+        let mut line_numbers: Vec<usize> = vec![];
+        let mut line = 1;
+        for token in &tokens {
+            line_numbers.push(line);
+            if *token == lexer::Token::NEWLINE { line += 1; }
+        }
+
+        Parser::with_lines(tokens, line_numbers)
+    }
+
+    pub fn with_lines(tokens: Vec<lexer::Token>, line_numbers: Vec<usize>) -> Self {
         let tok = tokens[0].clone();
-        Parser { tokens: tokens, position: 0, instruction_number: 0, tok: tok }
+        Parser { tokens: tokens, line_numbers: line_numbers, position: 0, instruction_number: 0, tok: tok, lines: vec![] }
     }
 
     pub fn peek(&self) -> lexer::Token {
@@ -46,23 +107,26 @@ impl Parser {
         self.tok = self.tokens[self.position].clone();
     }
 
-    pub fn parse(&mut self) -> (Vec<Instruction>, HashMap<String, u16>) {
+    pub fn parse(&mut self) -> Result<(Vec<Instruction>, HashMap<String, u16>)> {
         let mut symbol_table: HashMap<String, u16> = HashMap::new();
         let mut program: Vec<Instruction> = vec![];
 
         while self.tok != lexer::Token::EOF {
             match &self.tok {
                 lexer::Token::Label(identifier) => { 
-                    symbol_table.insert(identifier.clone(), self.instruction_number.try_into().unwrap()); 
+                    let address: u16 = self.instruction_number.try_into()
+                        .map_err(|_| Error::Parser(format!("program exceeds the address space, line: {}", self.line())))?;
+                    symbol_table.insert(identifier.clone(), address); 
                     self.eat_token();
                 }
                 lexer::Token::NEWLINE => { self.eat_token(); }
                 _ => {
-                    program.push(self.parse_instruction());
+                    self.lines.push(self.line_numbers[self.position]);
+                    program.push(self.parse_instruction()?);
                     self.instruction_number += 1;
                     self.eat_token();
                     if self.tok != lexer::Token::NEWLINE && self.tok != lexer::Token::EOF {
-                        panic!("expected newline, line: {}", self.instruction_number);
+                        return Err(Error::Parser(format!("expected newline, got: {:?}, line: {}", self.tok, self.line())));
                     } else if self.tok == lexer::Token::EOF {
                         break;
                     }
@@ -72,43 +136,49 @@ impl Parser {
             }
         }
 
-        (program, symbol_table)
+        Ok((program, symbol_table))
+    }
+
+    fn line(&self) -> usize {
+        self.line_numbers.get(self.position).cloned().unwrap_or(0)
     }
 
-    fn parse_instruction(&mut self) -> Instruction {
-        match &self.tok {
+    fn parse_instruction(&mut self) -> Result<Instruction> {
+        let instruction = match &self.tok {
             lexer::Token::HLT => Instruction::HLT,
-            lexer::Token::ADD => Instruction::ADD(self.parse_operand()),
-            lexer::Token::SUB => Instruction::SUB(self.parse_operand()),
-            lexer::Token::LDA => Instruction::LDA(self.parse_operand()),
-            lexer::Token::STA => Instruction::STA(self.parse_operand()),
-            lexer::Token::BRA => Instruction::BRA(self.parse_operand()),
-            lexer::Token::BRZ => Instruction::BRZ(self.parse_operand()),
-            lexer::Token::BGT => Instruction::BGT(self.parse_operand()),
-            lexer::Token::BLT => Instruction::BLT(self.parse_operand()),
+            lexer::Token::ADD => Instruction::ADD(self.parse_operand()?),
+            lexer::Token::SUB => Instruction::SUB(self.parse_operand()?),
+            lexer::Token::LDA => Instruction::LDA(self.parse_operand()?),
+            lexer::Token::STA => Instruction::STA(self.parse_operand()?),
+            lexer::Token::BRA => Instruction::BRA(self.parse_operand()?),
+            lexer::Token::BRZ => Instruction::BRZ(self.parse_operand()?),
+            lexer::Token::BGT => Instruction::BGT(self.parse_operand()?),
+            lexer::Token::BLT => Instruction::BLT(self.parse_operand()?),
             lexer::Token::INP => Instruction::INP,
             lexer::Token::OUT => Instruction::OUT,
             lexer::Token::OTC => Instruction::OTC,
-            lexer::Token::DAT => Instruction::DAT(self.parse_operand()),
-            lexer::Token::CALL => Instruction::CALL(self.parse_operand()),
+            lexer::Token::INC => Instruction::INC,
+            lexer::Token::DAT => Instruction::DAT(self.parse_operand()?),
+            lexer::Token::CALL => Instruction::CALL(self.parse_operand()?),
             lexer::Token::RET => Instruction::RET,
-            _ => { panic!("Unexpected token found in parse_instruction(): {:?}", self.tok)}
-        }
+            _ => { return Err(Error::Parser(format!("Unexpected token found in parse_instruction(): {:?}, line: {}", self.tok, self.line()))) }
+        };
 
+        Ok(instruction)
     }
 
-    fn parse_operand(&mut self) -> lexer::Token {
+    fn parse_operand(&mut self) -> Result<lexer::Token> {
         match self.peek() {
             lexer::Token::Label(_) | lexer::Token::Number(_) => { 
                 self.eat_token();
-                self.tok.clone() 
+                Ok(self.tok.clone())
             },
 
             lexer::Token::NEWLINE | lexer::Token::EOF => { 
-                lexer::Token::Number(0) 
+                Ok(lexer::Token::Number(0))
             },
 
-            _ => { panic!("unexpected token in operand, got: {:?}", self.peek()) }
+            _ => { Err(Error::Parser(format!("unexpected token in operand, got: {:?}, line: {}", self.peek(), self.line()))) }
         }
     }
 }
@@ -119,7 +189,7 @@ mod tests {
     #[test]
     fn test_parse_instruction() {
         let mut p = Parser::new(vec![lexer::Token::ADD, lexer::Token::Number(10), lexer::Token::EOF]);
-        let (prog, sym_table) = p.parse();
+        let (prog, sym_table) = p.parse().unwrap();
         assert_eq!(prog, vec![Instruction::ADD(lexer::Token::Number(10))]);
         assert_eq!(sym_table, HashMap::new());
     }
@@ -136,7 +206,7 @@ mod tests {
             lexer::Token::Label(String::from("RESULT")), lexer::Token::DAT, lexer::Token::EOF,
             ]);
 
-        let (prog, sym_table) = p.parse();
+        let (prog, sym_table) = p.parse().unwrap();
         assert_eq!(prog, vec![
             Instruction::LDA(lexer::Token::Label(String::from("ONE"))),
             Instruction::ADD(lexer::Token::Label(String::from("TWO"))),
@@ -151,5 +221,24 @@ mod tests {
             (String::from("TWO"), 4),
             (String::from("RESULT"), 5),
         ]));
+
+        assert_eq!(p.lines, vec![1, 2, 3, 5, 6, 7]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Instruction::LDA(lexer::Token::Label(String::from("ONE"))).to_string(), "lda ONE");
+        assert_eq!(Instruction::DAT(lexer::Token::Number(7)).to_string(), "dat 7");
+        assert_eq!(Instruction::RET.to_string(), "ret");
+    }
+
+    #[test]
+    fn test_expected_newline() {
+        let mut p = Parser::new(vec![
+            lexer::Token::NEWLINE,
+            lexer::Token::OUT, lexer::Token::OUT, lexer::Token::EOF,
+            ]);
+
+        assert_eq!(p.parse(), Err(Error::Parser(String::from("expected newline, got: OUT, line: 2"))));
+    }
+}
@@ -0,0 +1,24 @@
+use std::collections::HashMap;
+use crate::assembler::listing;
+use crate::assembler::object::Object;
+use crate::assembler::parser;
+use crate::error::Result;
+
+// An assembled program, the binary image alongside the instructions, symbols and source lines it was built from
+#[derive(Debug, PartialEq, Clone)]
+pub struct Program {
+    pub binary: Vec<u8>,
+    pub instructions: Vec<parser::Instruction>,
+    pub symbol_table: HashMap<String, u16>,
+    pub lines: Vec<usize>, // source line of each instruction
+}
+
+impl Program {
+    pub fn listing(&self, source: &str) -> Result<String> {
+        listing::Listing::new(&self.instructions, &self.symbol_table, &self.lines).render(source)
+    }
+
+    pub fn object(&self) -> Object {
+        Object::new(self.binary.clone(), self.symbol_table.clone(), self.lines.clone())
+    }
+}
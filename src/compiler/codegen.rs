@@ -0,0 +1,101 @@
+use std::collections::BTreeSet;
+
+use crate::compiler::ir::{Condition, Module, Op, Operand, Terminator};
+
+// Generates .lmasc assembly from the intermediate representation. The code is followed by the libraries and a data
+// section of globals, variables and constants, constants are given cells named after their value. The cell functions
+// return their value in comes last.
+pub fn generate(module: &Module) -> String {
+    generate_with_lines(module).0
+}
+
+// Generates assembly along with the source line of each instruction of the compiled code, taken from the Op::LINE
+// before it. The libraries and data section come after the compiled code and have no lines.
+pub fn generate_with_lines(module: &Module) -> (String, Vec<Option<usize>>) {
+    let mut constants: BTreeSet<i32> = BTreeSet::new();
+    let mut out = String::new();
+    let mut lines: Vec<Option<usize>> = vec![];
+    let mut line: Option<usize> = None;
+
+    for block in &module.blocks {
+        if let Some(label) = &block.label {
+            out += &format!("{label}\n");
+        }
+
+        for op in &block.ops {
+            let instruction = match op {
+                Op::LOAD(operand) => { format!("lda {}", operand_label(operand, &mut constants)) }
+                Op::STORE(identifier) => { format!("sta {identifier}") }
+                Op::ADD(operand) => { format!("add {}", operand_label(operand, &mut constants)) }
+                Op::SUB(operand) => { format!("sub {}", operand_label(operand, &mut constants)) }
+                Op::CALL(identifier) => { format!("call {identifier}") }
+                Op::LINE(number) => {
+                    line = Some(*number);
+                    continue;
+                }
+            };
+
+            out += &(instruction + "\n");
+            lines.push(line);
+        }
+
+        match &block.terminator {
+            Some(Terminator::JUMP(label)) => { out += &format!("bra {label}\n"); }
+            Some(Terminator::BRANCH(conditions, label)) => {
+                for condition in conditions {
+                    out += &format!("{} {label}\n", branch(*condition));
+                }
+            }
+            Some(Terminator::RETURN) => { out += "ret\n"; }
+            Some(Terminator::HALT) => { out += "hlt\n"; }
+            None => {}
+        }
+
+        let branches = match &block.terminator {
+            Some(Terminator::BRANCH(conditions, _)) => { conditions.len() }
+            Some(_) => { 1 }
+            None => { 0 }
+        };
+
+        lines.extend(std::iter::repeat_n(line, branches));
+    }
+
+    for (_, source) in &module.libraries {
+        out += source;
+        out += "\n";
+    }
+
+    for (identifier, value) in &module.globals {
+        out += &format!("{identifier} dat {value}\n");
+    }
+
+    for identifier in &module.variables {
+        if !module.globals.iter().any(|(global, _)| global == identifier) {
+            out += &format!("{identifier} dat 0\n");
+        }
+    }
+
+    for value in constants {
+        out += &format!("_{value} dat {value}\n");
+    }
+
+    (out + "_ret dat 0", lines)
+}
+
+fn operand_label(operand: &Operand, constants: &mut BTreeSet<i32>) -> String {
+    match operand {
+        Operand::Variable(identifier) => { identifier.clone() }
+        Operand::Constant(value) => {
+            constants.insert(*value);
+            format!("_{value}")
+        }
+    }
+}
+
+fn branch(condition: Condition) -> &'static str {
+    match condition {
+        Condition::ZERO => { "brz" }
+        Condition::POSITIVE => { "bgt" }
+        Condition::NEGATIVE => { "blt" }
+    }
+}
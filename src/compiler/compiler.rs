@@ -1,12 +1,35 @@
 use std::collections::HashMap;
 
+use crate::compiler::codegen;
+use crate::compiler::folder::Folder;
+use crate::compiler::ir::{Block, Condition, Module, Op, Operand, Terminator};
 use crate::compiler::node::Node;
 use crate::compiler::lexer::Token;
+use crate::error::{Error, Result};
 
+// Settings of a compilation
+pub struct Options {
+    pub opt_level: u8, // 0 emits the assembly as generated, 1 folds constants and runs the peephole optimizer
+    pub entry: Option<String>, // function the program starts in, main or _main when not given
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Options { opt_level: 0, entry: None }
+    }
+}
+
+// Lowers the syntax tree into the intermediate representation, which codegen turns into assembly
 pub struct Compiler {
-    constants: HashMap<i32, String>,
-    variables: HashMap<String, String>,
+    entry: Option<String>,
+    functions: Vec<String>,
     libraries: HashMap<String, String>,
+    blocks: Vec<Block>,
+    used: Vec<(String, String)>,
+    globals: Vec<(String, i32)>,
+    variables: Vec<String>,
+    tests: Vec<String>,
+    asserts: Vec<(String, String)>,
     label_index: i32,
 }
 
@@ -16,249 +39,436 @@ impl Compiler {
             // ("std".to_string(), std::path::PathBuf::from("src/compiler/linker/std.lmasc"))
             ("std".to_string(), include_str!("linker/std.lmasc").to_string())
         ].iter().cloned().collect();
-        Compiler { constants: HashMap::new(), variables: HashMap::new(), libraries: libraries, label_index: 0 }
+        Compiler {
+            entry: None, functions: vec![], libraries: libraries, blocks: vec![], used: vec![], globals: vec![], variables: vec![],
+            tests: vec![], asserts: vec![], label_index: 0
+        }
+    }
+
+
+    pub fn with_entry(entry: &str) -> Self {
+        let mut c = Compiler::new();
+        c.entry = Some(entry.to_string());
+        c
+    }
+
+
+    pub fn compile(&mut self, ast: Node) -> Result<String> {
+        Ok(codegen::generate(&self.lower(ast)?))
+    }
+
+
+    pub fn lower(&mut self, ast: Node) -> Result<Module> {
+        // the entry block calls the entry point and halts with its return value in the accumulator
+        self.blocks = vec![Block::new(None)];
+        self.terminate(Terminator::HALT);
+
+        match ast {
+            Node::BLOCK(statements) => {
+                for statement in *statements {
+                    self.lower_top_level(statement)?;
+                }
+            }
+            _ => { self.lower_top_level(ast)?; }
+        }
+
+        let entry = self.resolve_entry()?;
+        self.blocks[0].ops = vec![Op::CALL(entry), Op::LOAD(Operand::Variable("_ret".to_string()))];
+
+        Ok(Module {
+            blocks: std::mem::take(&mut self.blocks),
+            libraries: std::mem::take(&mut self.used),
+            globals: std::mem::take(&mut self.globals),
+            variables: std::mem::take(&mut self.variables),
+            tests: std::mem::take(&mut self.tests),
+            asserts: std::mem::take(&mut self.asserts),
+        })
+    }
+
+
+    fn resolve_entry(&self) -> Result<String> {
+        let defined = |name: &str| self.functions.iter().any(|function| function == name);
+        if let Some(entry) = &self.entry {
+            if !defined(entry) {
+                return Err(Error::Compiler(format!("entry point is not defined, got: {}", entry)));
+            }
+
+            return Ok(entry.clone());
+        }
+
+        match (defined("main"), defined("_main")) {
+            (true, true) => { Err(Error::Compiler(String::from("both main and _main are defined, choose the entry point"))) }
+            (true, false) => { Ok(String::from("main")) }
+            (false, true) => { Ok(String::from("_main")) }
+            (false, false) => { Err(Error::Compiler(String::from("no entry point, define fn main() or fn _main()"))) }
+        }
+    }
+
+
+    // Only libraries, functions and globals may appear outside a function, nothing executes there
+    fn lower_top_level(&mut self, node: Node) -> Result<()> {
+        match node {
+            Node::LIBRARY(library) => { self.lower_library(library) }
+            Node::FUNCTION(id, args, block) => { self.lower_function(id, args, *block) }
+            Node::ATTRIBUTE(name, function) => { self.lower_attribute(name, *function) }
+            Node::DECLARATION(identifier, expression) => { self.lower_global(identifier, *expression) }
+            _ => { Err(Error::Compiler(format!("executable statement outside a function, got: {}", node.kind()))) }
+        }
+    }
+
+
+    // #[test] marks a function as a unit test, run as the entry point of its own program
+    fn lower_attribute(&mut self, name: String, function: Node) -> Result<()> {
+        match (name.as_str(), function) {
+            ("test", Node::FUNCTION(id, args, block)) => {
+                if !args.is_empty() {
+                    return Err(Error::Compiler(format!("test function takes no arguments, got: {}", id)));
+                }
+
+                self.tests.push(id.clone());
+                self.lower_function(id, args, *block)
+            }
+            _ => { Err(Error::Compiler(format!("unknown attribute, got: {}", name))) }
+        }
     }
 
 
-    pub fn compile(&mut self, ast: Node) -> String {
-        let mut out = self.compile_node(ast);
-        for (value, label) in &self.constants {
-            out = out + &format!("{label} dat {value}\n");
+    fn lower_global(&mut self, identifier: String, expression_node: Node) -> Result<()> {
+        if self.globals.iter().any(|(global, _)| *global == identifier) {
+            return Err(Error::Compiler(format!("global declared more than once, got: {}", identifier)));
         }
 
-        out += "_ret dat 0";
-        "call _main\nhlt\n".to_owned() + &out 
+        // the initial value is stored in the cell when the program loads, so it has to be known at compile time
+        match Folder::new().fold(expression_node)? {
+            Node::NUMBER(value) => {
+                self.globals.push((identifier, value));
+                Ok(())
+            }
+            expression => { Err(Error::Compiler(format!("initial value of global {} is not a constant, got: {}", identifier, expression.kind()))) }
+        }
     }
 
 
-    fn compile_node(&mut self, node: Node) -> String {
+    fn lower_node(&mut self, node: Node) -> Result<()> {
         match node {
-            Node::BLOCK(statements) => { self.compile_block(*statements) }
-            Node::DECLARATION(identifier, expression) => { self.compile_declaration(identifier, *expression) }
-            Node::ASSIGNMENT(identifier, expression) => { self.compile_assignment(identifier, *expression) }
-            Node::INFIX(lhs, op, rhs) => { self.compile_infix(*lhs, op, *rhs) }
-            Node::INVOCATION(id, args) => { self.compile_invocation(id, *args) }
-            Node::LIBRARY(library) => { self.compile_library(library) }
-            Node::FUNCTION(id, args, block) => { self.compile_function(id, args, *block) }
-            Node::RETURN(expression) => { self.compile_return(*expression) }
-            Node::WHILE(condition, expression) => { self.compile_while(*condition, *expression) }
-            Node::FOR(declaration, condition, increment, consequence) => { self.compile_for(*declaration, *condition, *increment, *consequence) }
-            Node::IF(conditionals, alternative) => { self.compile_if(*conditionals, *alternative) }
-            Node::HALT() => { "hlt\n".to_string() }
-
-            Node::NUMBER(value) => { "lda ".to_owned() + &self.compile_number_literal(value) }
-            Node::IDENTIFIER(identifier) => { "lda ".to_owned() + &self.compile_identifier_literal(identifier) }
-
-            _ => { panic!("Unexpected node found in compile_node(), got: {:?}", node)}
+            Node::BLOCK(statements) => { self.lower_block(*statements) }
+            Node::DECLARATION(identifier, expression) => { self.lower_declaration(identifier, *expression) }
+            Node::ASSIGNMENT(identifier, expression) => { self.lower_assignment(identifier, *expression) }
+            Node::INFIX(lhs, op, rhs) => { self.lower_infix(*lhs, op, *rhs) }
+            Node::INVOCATION(id, args) => { self.lower_invocation(id, *args) }
+            Node::LIBRARY(library) => { self.lower_library(library) }
+            Node::FUNCTION(id, args, block) => { self.lower_function(id, args, *block) }
+            Node::RETURN(expression) => { self.lower_return(*expression) }
+            Node::WHILE(condition, expression) => { self.lower_while(*condition, *expression) }
+            Node::FOR(declaration, condition, increment, consequence) => { self.lower_for(*declaration, *condition, *increment, *consequence) }
+            Node::IF(conditionals, alternative) => { self.lower_if(*conditionals, *alternative) }
+            Node::HALT() => {
+                self.terminate(Terminator::HALT);
+                Ok(())
+            }
+            Node::LINE(line, node) => {
+                self.emit(Op::LINE(line));
+                self.lower_node(*node)
+            }
+
+            Node::NUMBER(value) => {
+                self.emit(Op::LOAD(Operand::Constant(value)));
+                Ok(())
+            }
+            Node::IDENTIFIER(identifier) => {
+                self.emit(Op::LOAD(Operand::Variable(identifier)));
+                Ok(())
+            }
+
+            _ => { Err(Error::Compiler(format!("Unexpected node found in compile_node(), got: {:?}", node))) }
         }
    }
 
 
-   fn compile_atom(&mut self, atom: Node) -> String {
+   fn lower_atom(&mut self, atom: Node) -> Result<Operand> {
         match atom {
-            Node::NUMBER(value) => { self.compile_number_literal(value) },
-            Node::IDENTIFIER(id) => { self.compile_identifier_literal(id) },
-            _ => { panic!("Unexpected node found in compile_node(), got: {:?}", atom)}
+            Node::NUMBER(value) => { Ok(Operand::Constant(value)) },
+            Node::IDENTIFIER(id) => { Ok(Operand::Variable(id)) },
+            _ => { Err(Error::Compiler(format!("Unexpected node found in compile_atom(), expected a number or identifier, got: {:?}", atom))) }
         }
    }
 
 
-    fn compile_block(&mut self, statements: Vec<Node>) -> String {
-        let mut out: String = String::new();
-        for node in statements.iter() {
-            out.push_str(&self.compile_node(node.clone()));
+    fn lower_block(&mut self, statements: Vec<Node>) -> Result<()> {
+        for node in statements {
+            self.lower_node(node)?;
         }
 
-        out
+        Ok(())
     }
 
 
-    fn compile_declaration(&mut self, identifier: String, expression_node: Node) -> String {
-        let expression = self.compile_node(expression_node);
-        self.variables.insert(identifier.clone(), identifier.clone());
-        format!("{identifier} dat 0\n{expression}\nsta {identifier}\n")
+    fn lower_declaration(&mut self, identifier: String, expression_node: Node) -> Result<()> {
+        self.declare(&identifier);
+        self.lower_node(expression_node)?;
+        self.emit(Op::STORE(identifier));
+        Ok(())
     }
 
-    
-    fn compile_assignment(&mut self, identifier: String, expression_node: Node) -> String {
-        let expression = self.compile_node(expression_node);
-        format!("{expression}\nsta {identifier}\n")
+
+    fn lower_assignment(&mut self, identifier: String, expression_node: Node) -> Result<()> {
+        self.lower_node(expression_node)?;
+        self.emit(Op::STORE(identifier));
+        Ok(())
     }
 
 
-    fn compile_infix(&mut self, lhs_node: Node, op_tok: Token, rhs_node: Node) -> String {
-        let lhs = self.compile_node(lhs_node);
-        let rhs = self.compile_atom(rhs_node);
+    fn lower_infix(&mut self, lhs_node: Node, op_tok: Token, rhs_node: Node) -> Result<()> {
+        self.lower_node(lhs_node)?;
+        let rhs = self.lower_atom(rhs_node)?;
 
-        let op;
+        // comparisons subtract, the branch following them tests the difference
         match op_tok {
-            Token::EE | Token::NE | Token::GT | Token::GTE | Token::LT | Token::LTE  => { op = Token::SUB; }
-            _ => { op = op_tok; }
+            Token::ADD => { self.emit(Op::ADD(rhs)) }
+            _ => { self.emit(Op::SUB(rhs)) }
         }
 
-        format!("{lhs}\n{:?} {rhs}", op)
+        Ok(())
     }
 
 
-    fn compile_number_literal(&mut self, value: i32) -> String {
-        if self.constants.contains_key(&value) {
-            self.constants.get(&value).unwrap().clone()
-        } else {
-            self.constants.insert(value, "_".to_owned() + &value.to_string());
-            self.constants.get(&value).unwrap().clone()
-        }
+    fn lower_library(&mut self, library: String) -> Result<()> {
+        let lib_content: String = self.libraries.get(&library).ok_or(Error::Compiler(format!("No library exists with name: {}", library)))?.clone();
+        self.used.push((library, lib_content));
+        Ok(())
     }
 
 
-    fn compile_identifier_literal(&mut self, identifier: String) -> String {
-        identifier
-    }
+    fn lower_invocation(&mut self, identifier: String, args: Vec<Node>) -> Result<()> {
+        if identifier == "assert" || identifier == "assert_eq" {
+            return self.lower_assert(identifier, args);
+        }
 
+        for (arg_counter, arg) in args.into_iter().enumerate() {
+            let arg_id = "_p".to_owned() + &arg_counter.to_string();
+            self.lower_declaration(arg_id, arg)?;
+        }
 
-    fn compile_library(&mut self, library: String) -> String {
-        let lib_content: String = self.libraries.get(&library).expect(&format!("No library exists with name: {}", library)).clone();
-        // let lib_content = std::fs::read_to_string(lib_path).expect("could not read library");
-        lib_content + "\n"
+        self.emit(Op::CALL(identifier));
+        self.emit(Op::LOAD(Operand::Variable("_ret".to_string())));
+        Ok(())
     }
 
 
-    fn compile_invocation(&mut self, identifier: String, args: Vec<Node>) -> String {
-        let mut arg_counter = 0;
-        let mut arg_out: String = String::new();
-        for arg in args {
-            let arg_id = "_p".to_owned() + &arg_counter.to_string();
-            arg_out.push_str(&self.compile_node(Node::DECLARATION(
-                arg_id, 
-                Box::new(arg),
-            )));
+    // A failing assertion halts at a hlt of its own, its label tells which assertion failed
+    fn lower_assert(&mut self, identifier: String, args: Vec<Node>) -> Result<()> {
+        let arguments: Vec<String> = args.iter().map(|arg| arg.source()).collect();
+        let description = format!("{}({})", identifier, arguments.join(", "));
 
-            arg_counter += 1;
-        }
+        let mut args = args.into_iter();
+        let condition = match (identifier.as_str(), args.next(), args.next(), args.next()) {
+            ("assert", Some(condition), None, None) => { condition }
+            ("assert_eq", Some(lhs), Some(rhs), None) => { Node::INFIX(Box::new(lhs), Token::EE, Box::new(rhs)) }
+            _ => { return Err(Error::Compiler(format!("wrong number of arguments to {}, got: {}", identifier, description))); }
+        };
+
+        let passed = self.generate_label("_l");
+        let failed = self.generate_label("_assert");
+        self.asserts.push((failed.clone(), description));
+
+        let branches = self.get_conditional_branch(&condition);
+        self.lower_node(condition)?;
+        self.terminate(Terminator::BRANCH(branches, passed.clone()));
+
+        self.start_block(Some(failed));
+        self.terminate(Terminator::HALT);
 
-        format!("{arg_out}call {identifier}\nlda _ret\n")
+        self.start_block(Some(passed));
+        Ok(())
     }
 
 
-    fn compile_function(&mut self, identifier: String, args: Vec<String>, block: Node) -> String {
-        let mut args_out: String = String::new();
-        let mut arg_counter = 0;
+    fn lower_function(&mut self, identifier: String, args: Vec<String>, block: Node) -> Result<()> {
+        if self.functions.contains(&identifier) {
+            return Err(Error::Compiler(format!("function defined more than once, got: {}", identifier)));
+        }
+
+        self.functions.push(identifier.clone());
+        self.start_block(Some(identifier));
+        let line = line_of(&block);
+        self.mark_line(line);
 
-        for arg in args {
-            self.variables.insert(arg.clone(), arg.clone());
+        for (arg_counter, arg) in args.into_iter().enumerate() {
             let arg_id = "_p".to_owned() + &arg_counter.to_string();
+            self.declare(&arg_id);
+            self.lower_declaration(arg, Node::IDENTIFIER(arg_id))?;
+        }
 
-            args_out.push_str(&self.compile_node(Node::DECLARATION(
-                arg, 
-                Box::new(Node::IDENTIFIER(arg_id))
-            )));
+        self.lower_node(block)?;
 
-            arg_counter += 1;
+        // falling off the end returns 0, on the line of the fn rather than of the last statement laid out before it
+        if self.current().terminator.is_none() {
+            self.mark_line(line);
+            self.lower_return(Node::NUMBER(0))?;
         }
 
-        format!("{identifier}\n{args_out}{}ret\n", self.compile_node(block))
+        Ok(())
     }
 
-    
-    fn compile_if(&mut self, conditionals: Vec<Node>, alternative: Node) -> String {
-        //Loop through conditionals, branch if condition to corresponding label (& postfix each with bra .endif)
-        //Follow with <else> instructions followed with bra .endif
+
+    fn lower_if(&mut self, conditionals: Vec<Node>, alternative: Node) -> Result<()> {
+        // Each condition branches to its consequence, the alternative follows the conditions and every
+        // consequence is laid out after it, all of them jumping to the end. The jumps are on the line of the if or
+        // elif whose branch they end.
         let endif = self.generate_label("_l");
 
-        let mut compiled_conditions = String::new();
-        let mut compiled_consequences = String::new();
+        let mut consequences: Vec<(String, Node, Option<usize>)> = vec![];
         for condition in conditionals {
             let condition_label = self.generate_label("_l");
-            // let compiled_condition: String = String::new();
 
             if let Node::CONDITIONAL(condition_node, consequence) = condition {
-                let branches: Vec<String> = self.get_conditional_branch(&condition_node);
-                let condition_expr = self.compile_node(*condition_node);        
-
-                let mut compiled_branches: String = String::new();
-                for branch in branches {
-                    compiled_branches += &format!("{branch} {condition_label}\n");
-                }
+                let line = line_of(&condition_node);
+                let branches = self.get_conditional_branch(&condition_node);
+                self.lower_node(*condition_node)?;
+                self.terminate(Terminator::BRANCH(branches, condition_label.clone()));
+                consequences.push((condition_label, *consequence, line));
+            }
+        }
 
-                let compiled_consequence = self.compile_node(*consequence);
+        let line = consequences.first().and_then(|(_, _, line)| *line);
+        self.lower_node(alternative)?;
+        self.mark_line(line);
+        self.terminate(Terminator::JUMP(endif.clone()));
 
-                compiled_conditions += &format!("{condition_expr}\n{compiled_branches}");
-                compiled_consequences += &format!("{condition_label}\n{compiled_consequence}bra {endif}\n");
-            }
+        for (label, consequence, line) in consequences {
+            self.start_block(Some(label));
+            self.lower_node(consequence)?;
+            self.mark_line(line);
+            self.terminate(Terminator::JUMP(endif.clone()));
         }
 
-        let compiled_alternative = self.compile_node(alternative);
-        format!("{compiled_conditions}{compiled_alternative}bra {endif}\n{compiled_consequences}{endif}\n")
+        self.start_block(Some(endif));
+        Ok(())
     }
 
 
-    fn compile_while(&mut self, condition_node: Node, consequence_node: Node) -> String {
+    fn lower_while(&mut self, condition_node: Node, consequence_node: Node) -> Result<()> {
         let beginwhile = self.generate_label("_l");
         let consequence = self.generate_label("_l");
         let endwhile = self.generate_label("_l");
+        let line = line_of(&condition_node);
 
-        let branches: Vec<String> = self.get_conditional_branch(&condition_node);
-        let compiled_conditional = self.compile_node(condition_node);        
+        self.start_block(Some(beginwhile.clone()));
+        let branches = self.get_conditional_branch(&condition_node);
+        self.lower_node(condition_node)?;
+        self.terminate(Terminator::BRANCH(branches, consequence.clone()));
+        self.terminate(Terminator::JUMP(endwhile.clone()));
 
-        let mut compiled_branches: String = String::new();
-        for branch in branches {
-            compiled_branches += &format!("{branch} {consequence}\n");
-        }
-
-        let compiled_consequence = self.compile_node(consequence_node);
+        self.start_block(Some(consequence));
+        self.lower_node(consequence_node)?;
+        self.mark_line(line);
+        self.terminate(Terminator::JUMP(beginwhile));
 
-        format!("{beginwhile}\n{compiled_conditional}\n{compiled_branches}bra {endwhile}\n{consequence}\n{compiled_consequence}bra {beginwhile}\n{endwhile}\n")
+        self.start_block(Some(endwhile));
+        Ok(())
     }
 
 
-    fn compile_for(&mut self, declaration_node: Node, condition_node: Node, increment_node: Node, consequence_node: Node) -> String {
+    fn lower_for(&mut self, declaration_node: Node, condition_node: Node, increment_node: Node, consequence_node: Node) -> Result<()> {
         let loop_label = self.generate_label("_l");
         let conseq_label = self.generate_label("_l");
         let endloop_label = self.generate_label("_l");
 
-        let declaration = self.compile_node(declaration_node);
+        self.lower_node(declaration_node)?;
 
-        let branch_instructions: Vec<String> = self.get_conditional_branch(&condition_node);
-        let condition = self.compile_node(condition_node);        
-
-        let mut branches: String = String::new();
-        for branch in branch_instructions {
-            branches += &format!("{branch} {conseq_label}\n");
-        }
+        self.start_block(Some(loop_label.clone()));
+        let branches = self.get_conditional_branch(&condition_node);
+        self.lower_node(condition_node)?;
+        self.terminate(Terminator::BRANCH(branches, conseq_label.clone()));
+        self.terminate(Terminator::JUMP(endloop_label.clone()));
 
-        let consequence = self.compile_node(consequence_node);
-        let increment = self.compile_node(increment_node);
+        self.start_block(Some(conseq_label));
+        self.lower_node(consequence_node)?;
+        self.lower_node(increment_node)?;
+        self.terminate(Terminator::JUMP(loop_label));
 
-        format!("{declaration}{loop_label}\n{condition}\n{branches}bra {endloop_label}\n{conseq_label}\n{consequence}{increment}bra {loop_label}\n{endloop_label}\n")
+        self.start_block(Some(endloop_label));
+        Ok(())
     }
 
 
-    fn get_conditional_branch(&self, infix: &Node) -> Vec<String> {
+    fn get_conditional_branch(&self, infix: &Node) -> Vec<Condition> {
         match infix {
+            Node::LINE(_, node) => { self.get_conditional_branch(node) }
             Node::INFIX(_, op, _) => {
                 match op {
-                    Token::EE => { vec!["brz".to_string()] }
-                    Token::NE => { vec!["bgt".to_string()] }
+                    Token::EE => { vec![Condition::ZERO] }
+                    Token::NE => { vec![Condition::POSITIVE] }
 
-                    Token::LT => { vec!["blt".to_string()] }
-                    Token::GT => { vec!["bgt".to_string()] }
+                    Token::LT => { vec![Condition::NEGATIVE] }
+                    Token::GT => { vec![Condition::POSITIVE] }
 
-                    Token::LTE => { vec!["blt".to_string(), "brz".to_string()] }
-                    Token::GTE => { vec!["bgt".to_string(), "brz".to_string()] }
+                    Token::LTE => { vec![Condition::NEGATIVE, Condition::ZERO] }
+                    Token::GTE => { vec![Condition::POSITIVE, Condition::ZERO] }
 
-                    _ => { vec!["bgt".to_string()] }
+                    _ => { vec![Condition::POSITIVE] }
                 }
             }
-            _ => { vec!["bgt".to_string()] }
+            _ => { vec![Condition::POSITIVE] }
         }
     }
 
 
-    fn compile_return(&mut self, expression_node: Node) -> String {
-        let expr_out = self.compile_node(Node::DECLARATION(
-            "_ret".to_string(), 
-            Box::new(expression_node)
-        ));
-        format!("{expr_out}ret\n")
+    fn lower_return(&mut self, expression_node: Node) -> Result<()> {
+        self.lower_assignment("_ret".to_string(), expression_node)?;
+        self.terminate(Terminator::RETURN);
+        Ok(())
+    }
+
+
+    // Gives a variable a cell in the data section
+    fn declare(&mut self, identifier: &str) {
+        if !self.variables.iter().any(|variable| variable == identifier) {
+            self.variables.push(identifier.to_string());
+        }
+    }
+
+
+    fn emit(&mut self, op: Op) {
+        if self.current().terminator.is_some() {
+            self.start_block(None);
+        }
+
+        self.current().ops.push(op);
+    }
+
+
+    // Ends the current block, code lowered after it goes into a new block
+    fn terminate(&mut self, terminator: Terminator) {
+        if self.current().terminator.is_some() {
+            self.start_block(None);
+        }
+
+        self.current().terminator = Some(terminator);
+    }
+
+
+    // Puts the code lowered next on a line, unless the current block has ended and no code can follow
+    fn mark_line(&mut self, line: Option<usize>) {
+        if let Some(line) = line.filter(|_| self.current().terminator.is_none()) {
+            self.emit(Op::LINE(line));
+        }
+    }
+
+
+    fn start_block(&mut self, label: Option<String>) {
+        let current = self.current();
+        if current.label.is_none() && current.ops.is_empty() && current.terminator.is_none() {
+            current.label = label;
+            return;
+        }
+
+        self.blocks.push(Block::new(label));
+    }
+
+
+    fn current(&mut self) -> &mut Block {
+        self.blocks.last_mut().unwrap()
     }
 
 
@@ -266,7 +476,16 @@ impl Compiler {
         let label = id.to_owned() + &self.label_index.to_string();
         self.label_index += 1;
         label
-    } 
+    }
+}
+
+
+// Line a statement or condition was parsed on, when the parser tracked lines
+fn line_of(node: &Node) -> Option<usize> {
+    match node {
+        Node::LINE(line, _) => { Some(*line) }
+        _ => { None }
+    }
 }
 
 
@@ -274,34 +493,128 @@ impl Compiler {
 mod tests {
     use super::*;
 
+    fn function(statements: Vec<Node>) -> Node {
+        Node::BLOCK(Box::new(vec![Node::FUNCTION(String::from("_main"), vec![], Box::new(Node::BLOCK(Box::new(statements))))]))
+    }
+
     #[test]
     fn test_compile_declaration() {
         let mut c = Compiler::new();
-        assert_eq!(c.compile(Node::BLOCK(Box::new(vec![
+        assert_eq!(c.compile(function(vec![
             Node::DECLARATION(
-                String::from("x"), 
+                String::from("x"),
                 Box::new(Node::NUMBER(1)),
             )]
-        ))), 
+        )),
 
-        String::from("call _main\nhlt\nx dat 0\nlda _1\nsta x\n_1 dat 1\n_ret dat 0")
+        Ok(String::from("call _main\nlda _ret\nhlt\n_main\nlda _1\nsta x\nlda _0\nsta _ret\nret\nx dat 0\n_0 dat 0\n_1 dat 1\n_ret dat 0"))
         );
     }
 
     #[test]
     fn test_compile_infix() {
         let mut c = Compiler::new();
-        let out: String = c.compile(Node::BLOCK(Box::new(vec![
+        let out: String = c.compile(function(vec![
             Node::DECLARATION(
-                String::from("x"), 
+                String::from("x"),
                 Box::new(Node::INFIX(
-                    Box::new(Node::NUMBER(1)), 
-                    Token::ADD, 
+                    Box::new(Node::NUMBER(1)),
+                    Token::ADD,
                     Box::new(Node::NUMBER(2)))),
             )]
-        )));
+        )).unwrap();
 
-        assert_eq!(out[0..43], 
-        String::from("call _main\nhlt\nx dat 0\nlda _1\nADD _2\nsta x\n"));
+        assert_eq!(out[0..50],
+        String::from("call _main\nlda _ret\nhlt\n_main\nlda _1\nadd _2\nsta x\n"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_compile_global() {
+        let mut c = Compiler::new();
+        let out: String = c.compile(Node::BLOCK(Box::new(vec![
+            Node::DECLARATION(
+                String::from("x"),
+                Box::new(Node::INFIX(
+                    Box::new(Node::NUMBER(1)),
+                    Token::ADD,
+                    Box::new(Node::NUMBER(2)))),
+            ),
+            Node::FUNCTION(String::from("main"), vec![], Box::new(Node::BLOCK(Box::default()))),
+            ]
+        ))).unwrap();
+
+        assert_eq!(out, "call main\nlda _ret\nhlt\nmain\nlda _0\nsta _ret\nret\nx dat 3\n_0 dat 0\n_ret dat 0");
+    }
+
+    #[test]
+    fn test_top_level_statement() {
+        let mut c = Compiler::new();
+        assert_eq!(c.compile(Node::BLOCK(Box::new(vec![Node::INVOCATION(String::from("print"), Box::new(vec![Node::NUMBER(1)]))]))),
+            Err(Error::Compiler(String::from("executable statement outside a function, got: INVOCATION"))));
+
+        let mut c = Compiler::new();
+        assert_eq!(c.compile(Node::BLOCK(Box::new(vec![Node::DECLARATION(String::from("x"), Box::new(Node::IDENTIFIER(String::from("y"))))]))),
+            Err(Error::Compiler(String::from("initial value of global x is not a constant, got: IDENTIFIER"))));
+    }
+
+    #[test]
+    fn test_compile_unknown_library() {
+        let mut c = Compiler::new();
+        assert_eq!(c.compile(Node::BLOCK(Box::new(vec![Node::LIBRARY(String::from("maths"))]))),
+            Err(Error::Compiler(String::from("No library exists with name: maths"))));
+    }
+
+    #[test]
+    fn test_entry_point() {
+        let main = |name: &str| Node::FUNCTION(String::from(name), vec![], Box::new(Node::BLOCK(Box::default())));
+
+        let mut c = Compiler::new();
+        assert_eq!(c.compile(Node::BLOCK(Box::new(vec![main("f")]))),
+            Err(Error::Compiler(String::from("no entry point, define fn main() or fn _main()"))));
+
+        let mut c = Compiler::new();
+        assert!(c.compile(Node::BLOCK(Box::new(vec![main("main"), main("_main")]))).is_err());
+
+        let mut c = Compiler::with_entry("f");
+        assert!(c.compile(Node::BLOCK(Box::new(vec![main("f")]))).unwrap().starts_with("call f\n"));
+
+        let mut c = Compiler::with_entry("g");
+        assert_eq!(c.compile(Node::BLOCK(Box::new(vec![main("f")]))),
+            Err(Error::Compiler(String::from("entry point is not defined, got: g"))));
+    }
+
+    #[test]
+    fn test_lower_test() {
+        let assertion = Node::INVOCATION(String::from("assert_eq"), Box::new(vec![Node::IDENTIFIER(String::from("x")), Node::NUMBER(1)]));
+        let test = Node::ATTRIBUTE(String::from("test"), Box::new(Node::FUNCTION(String::from("t"), vec![], Box::new(assertion))));
+
+        let mut c = Compiler::with_entry("t");
+        let module = c.lower(Node::BLOCK(Box::new(vec![test]))).unwrap();
+        assert_eq!(module.tests, vec![String::from("t")]);
+        assert_eq!(module.asserts, vec![(String::from("_assert1"), String::from("assert_eq(x, 1)"))]);
+        assert_eq!(module.blocks[1].terminator, Some(Terminator::BRANCH(vec![Condition::ZERO], String::from("_l0"))));
+        assert_eq!(module.blocks[2], Block { label: Some(String::from("_assert1")), ops: vec![], terminator: Some(Terminator::HALT) });
+
+        let mut c = Compiler::new();
+        let bench = Node::ATTRIBUTE(String::from("bench"), Box::new(Node::FUNCTION(String::from("main"), vec![], Box::new(Node::BLOCK(Box::default())))));
+        assert_eq!(c.compile(Node::BLOCK(Box::new(vec![bench]))), Err(Error::Compiler(String::from("unknown attribute, got: bench"))));
+    }
+
+    #[test]
+    fn test_lower_while() {
+        let mut c = Compiler::new();
+        let module = c.lower(Node::BLOCK(Box::new(vec![Node::FUNCTION(String::from("_main"), vec![], Box::new(
+            Node::WHILE(
+                Box::new(Node::INFIX(Box::new(Node::IDENTIFIER(String::from("x"))), Token::LT, Box::new(Node::NUMBER(3)))),
+                Box::new(Node::ASSIGNMENT(String::from("x"), Box::new(Node::NUMBER(3)))),
+            ),
+        ))]))).unwrap();
+
+        let labels: Vec<Option<&str>> = module.blocks.iter().map(|block| block.label.as_deref()).collect();
+        assert_eq!(labels, vec![None, Some("_main"), Some("_l0"), None, Some("_l1"), Some("_l2")]);
+        assert_eq!(module.blocks[2].ops, vec![Op::LOAD(Operand::Variable(String::from("x"))), Op::SUB(Operand::Constant(3))]);
+        assert_eq!(module.blocks[2].terminator, Some(Terminator::BRANCH(vec![Condition::NEGATIVE], String::from("_l1"))));
+        assert_eq!(module.blocks[3].terminator, Some(Terminator::JUMP(String::from("_l2"))));
+        assert_eq!(module.blocks[5].terminator, Some(Terminator::RETURN));
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::{HashMap, HashSet};
+use crate::compiler::lexer::Token;
+use crate::compiler::node::Node;
+use crate::error::{Error, Result};
+
+const WORD_MAX: i32 = u16::MAX as i32; // largest value of a machine word
+
+// Constant folding and propagation over the syntax tree. Additions and subtractions of constants are evaluated at
+// compile time and variables holding a known constant are replaced by it. Every variable is a global cell which any
+// function may write, so known values are forgotten across invocations and for the variables a loop or branch assigns.
+// Only expressions written with literals alone are checked against the word's range, a propagated value may wrap at
+// run time like the machine's arithmetic does, so such an expression is left to the machine.
+pub struct Folder {
+    known: HashMap<String, i32>,
+    propagate: bool,
+}
+
+impl Folder {
+    pub fn new() -> Self {
+        Folder { known: HashMap::new(), propagate: true }
+    }
+
+    // A folder which only folds literal expressions, checking their range without replacing any variable
+    pub fn literals() -> Self {
+        Folder { known: HashMap::new(), propagate: false }
+    }
+
+    pub fn fold(&mut self, node: Node) -> Result<Node> {
+        match node {
+            Node::BLOCK(statements) => {
+                let mut folded: Vec<Node> = vec![];
+                for statement in *statements {
+                    folded.push(self.fold(statement)?);
+                }
+
+                Ok(Node::BLOCK(Box::new(folded)))
+            }
+            Node::DECLARATION(identifier, expression) => {
+                let expression = self.fold(*expression)?;
+                self.assign(&identifier, &expression);
+                Ok(Node::DECLARATION(identifier, Box::new(expression)))
+            }
+            Node::ASSIGNMENT(identifier, expression) => {
+                let expression = self.fold(*expression)?;
+                self.assign(&identifier, &expression);
+                Ok(Node::ASSIGNMENT(identifier, Box::new(expression)))
+            }
+            Node::INFIX(lhs, op, rhs) => { self.fold_infix(*lhs, op, *rhs) }
+            Node::INVOCATION(identifier, arguments) => {
+                let mut folded: Vec<Node> = vec![];
+                for argument in *arguments {
+                    folded.push(self.fold(argument)?);
+                }
+
+                self.known.clear();
+                Ok(Node::INVOCATION(identifier, Box::new(folded)))
+            }
+            Node::FUNCTION(identifier, parameters, body) => {
+                // a function may be called from anywhere, nothing is known on entry or kept after it
+                let outer = std::mem::take(&mut self.known);
+                let body = self.fold(*body)?;
+                self.known = outer;
+                Ok(Node::FUNCTION(identifier, parameters, Box::new(body)))
+            }
+            Node::ATTRIBUTE(name, function) => { Ok(Node::ATTRIBUTE(name, Box::new(self.fold(*function)?))) }
+            Node::LINE(line, node) => { Ok(Node::LINE(line, Box::new(self.fold(*node)?))) }
+            Node::RETURN(expression) => { Ok(Node::RETURN(Box::new(self.fold(*expression)?))) }
+            Node::IF(conditionals, alternative) => { self.fold_if(*conditionals, *alternative) }
+            Node::WHILE(condition, consequence) => {
+                self.forget(&[&condition, &consequence]);
+                let condition = self.fold(*condition)?;
+                let consequence = self.fold(*consequence)?;
+                self.forget(&[&condition, &consequence]);
+                Ok(Node::WHILE(Box::new(condition), Box::new(consequence)))
+            }
+            Node::FOR(declaration, condition, increment, consequence) => {
+                let declaration = self.fold(*declaration)?;
+                self.forget(&[&condition, &increment, &consequence]);
+                let condition = self.fold(*condition)?;
+                let consequence = self.fold(*consequence)?;
+                let increment = self.fold(*increment)?;
+                self.forget(&[&condition, &increment, &consequence]);
+                Ok(Node::FOR(Box::new(declaration), Box::new(condition), Box::new(increment), Box::new(consequence)))
+            }
+
+            Node::IDENTIFIER(identifier) => {
+                match self.known.get(&identifier) {
+                    Some(value) if self.propagate => { Ok(Node::NUMBER(*value)) }
+                    _ => { Ok(Node::IDENTIFIER(identifier)) }
+                }
+            }
+            Node::NUMBER(value) => {
+                if !(0..=WORD_MAX).contains(&value) {
+                    return Err(Error::Compiler(format!("constant does not fit in a 16-bit word, got: {}", value)));
+                }
+
+                Ok(Node::NUMBER(value))
+            }
+
+            _ => { Ok(node) }
+        }
+    }
+
+    fn fold_infix(&mut self, lhs: Node, op: Token, rhs: Node) -> Result<Node> {
+        let literal = is_literal(&lhs) && is_literal(&rhs);
+        let lhs = self.fold(lhs)?;
+        let rhs = self.fold(rhs)?;
+
+        let (a, b) = match (&lhs, &rhs) {
+            (Node::NUMBER(a), Node::NUMBER(b)) => { (*a, *b) }
+            _ => { return Ok(Node::INFIX(Box::new(lhs), op, Box::new(rhs))) }
+        };
+
+        let value = match op {
+            Token::ADD => { a + b }
+            Token::SUB => { a - b }
+            _ => { return Ok(Node::INFIX(Box::new(lhs), op, Box::new(rhs))) }
+        };
+
+        if !(0..=WORD_MAX).contains(&value) {
+            if !literal {
+                return Ok(Node::INFIX(Box::new(lhs), op, Box::new(rhs)));
+            }
+
+            return Err(Error::Compiler(format!("constant expression overflows a 16-bit word, got: {} {} {} = {}", a, op, b, value)));
+        }
+
+        Ok(Node::NUMBER(value))
+    }
+
+    fn fold_if(&mut self, conditionals: Vec<Node>, alternative: Node) -> Result<Node> {
+        // conditions run in order, each branch starts from what is known after its condition
+        let mut branches: Vec<Node> = vec![];
+        let mut folded: Vec<Node> = vec![];
+        for conditional in conditionals {
+            if let Node::CONDITIONAL(condition, consequence) = conditional {
+                let condition = self.fold(*condition)?;
+
+                let known = self.known.clone();
+                let consequence = self.fold(*consequence)?;
+                self.known = known;
+
+                branches.push(consequence.clone());
+                folded.push(Node::CONDITIONAL(Box::new(condition), Box::new(consequence)));
+            }
+        }
+
+        let known = self.known.clone();
+        let alternative = self.fold(alternative)?;
+        self.known = known;
+
+        branches.push(alternative.clone());
+        self.forget(&branches.iter().collect::<Vec<&Node>>());
+        Ok(Node::IF(Box::new(folded), Box::new(alternative)))
+    }
+
+    fn assign(&mut self, identifier: &str, expression: &Node) {
+        match expression {
+            Node::NUMBER(value) => { self.known.insert(identifier.to_string(), *value); }
+            _ => { self.known.remove(identifier); }
+        }
+    }
+
+    // Forgets the variables the nodes may write, everything when they invoke a function
+    fn forget(&mut self, nodes: &[&Node]) {
+        let mut assigned: HashSet<String> = HashSet::new();
+        for node in nodes {
+            if !assignments(node, &mut assigned) {
+                self.known.clear();
+                return;
+            }
+        }
+
+        self.known.retain(|identifier, _| !assigned.contains(identifier));
+    }
+}
+
+// Whether an expression is written with literals alone
+fn is_literal(node: &Node) -> bool {
+    match node {
+        Node::NUMBER(_) => { true }
+        Node::INFIX(lhs, _, rhs) => { is_literal(lhs) && is_literal(rhs) }
+        _ => { false }
+    }
+}
+
+// Collects the variables a node assigns, returns false when it invokes a function which may assign any
+fn assignments(node: &Node, assigned: &mut HashSet<String>) -> bool {
+    match node {
+        Node::DECLARATION(identifier, expression) | Node::ASSIGNMENT(identifier, expression) => {
+            assigned.insert(identifier.clone());
+            assignments(expression, assigned)
+        }
+        Node::INVOCATION(_, _) => { false }
+        Node::BLOCK(statements) => { statements.iter().all(|statement| assignments(statement, assigned)) }
+        Node::INFIX(lhs, _, rhs) => { assignments(lhs, assigned) && assignments(rhs, assigned) }
+        Node::RETURN(expression) | Node::LINE(_, expression) => { assignments(expression, assigned) }
+        Node::IF(conditionals, alternative) => {
+            conditionals.iter().all(|conditional| assignments(conditional, assigned)) && assignments(alternative, assigned)
+        }
+        Node::CONDITIONAL(condition, consequence) | Node::WHILE(condition, consequence) => {
+            assignments(condition, assigned) && assignments(consequence, assigned)
+        }
+        Node::FOR(declaration, condition, increment, consequence) => {
+            assignments(declaration, assigned) && assignments(condition, assigned) &&
+                assignments(increment, assigned) && assignments(consequence, assigned)
+        }
+        _ => { true }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fold(source: &str) -> Result<Node> {
+        let mut l = crate::compiler::lexer::Lexer::new(source.chars().collect());
+        let mut p = crate::compiler::parser::Parser::new(l.lex()?);
+        Folder::new().fold(p.parse()?)
+    }
+
+    fn statements(node: Node) -> Vec<Node> {
+        match node {
+            Node::BLOCK(statements) => { *statements }
+            _ => { panic!("expected a block, got: {:?}", node) }
+        }
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let ast = statements(fold("let x = 3 + (2 - 1);").unwrap());
+        assert_eq!(ast[0], Node::DECLARATION(String::from("x"), Box::new(Node::NUMBER(4))));
+    }
+
+    #[test]
+    fn test_propagate() {
+        let ast = statements(fold("let x = 3;\nlet y = x + 1;\nx = y - x;\nprint(x);").unwrap());
+        assert_eq!(ast[1], Node::DECLARATION(String::from("y"), Box::new(Node::NUMBER(4))));
+        assert_eq!(ast[2], Node::ASSIGNMENT(String::from("x"), Box::new(Node::NUMBER(1))));
+        assert_eq!(ast[3], Node::INVOCATION(String::from("print"), Box::new(vec![Node::NUMBER(1)])));
+    }
+
+    #[test]
+    fn test_forget_in_loop() {
+        let ast = statements(fold("let i = 0;\nwhile (i < 3) {\n    i = i + 1;\n}\nlet j = i;").unwrap());
+        assert_eq!(ast[2], Node::DECLARATION(String::from("j"), Box::new(Node::IDENTIFIER(String::from("i")))));
+    }
+
+    #[test]
+    fn test_forget_after_invocation() {
+        let ast = statements(fold("let x = 1;\nf();\nlet y = x;").unwrap());
+        assert_eq!(ast[2], Node::DECLARATION(String::from("y"), Box::new(Node::IDENTIFIER(String::from("x")))));
+    }
+
+    #[test]
+    fn test_overflow() {
+        assert_eq!(fold("let x = 65535 + 1;"),
+            Err(Error::Compiler(String::from("constant expression overflows a 16-bit word, got: 65535 + 1 = 65536"))));
+        assert!(fold("let x = 1 - 2;").is_err());
+        assert!(fold("let x = 70000;").is_err());
+    }
+
+    #[test]
+    fn test_overflow_after_propagation() {
+        // the value wraps at run time, only literal expressions are range checked
+        let ast = statements(fold("let x = 0;\nx = x - 1;").unwrap());
+        assert_eq!(ast[1], Node::ASSIGNMENT(String::from("x"),
+            Box::new(Node::INFIX(Box::new(Node::NUMBER(0)), Token::SUB, Box::new(Node::NUMBER(1))))));
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+
+// Intermediate representation between the syntax tree and assembly
+//
+// A module is a list of basic blocks laid out in order. A block is a run of accumulator operations ending in a
+// terminator, and a block without a terminator falls through to the next one. Only labelled blocks can be the target
+// of a jump or branch. Values live in named variable cells or constants, the accumulator holds intermediate results.
+// Variable cells are placed in a data section after the code, globals hold their initial value when loaded.
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Operand {
+    Variable(String),
+    Constant(i32),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Op {
+    LOAD(Operand),
+    STORE(String),
+    ADD(Operand),
+    SUB(Operand),
+    CALL(String),
+    LINE(usize), // the ops after it come from this source line, generates no code
+}
+
+// State of the accumulator a branch is taken on
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Condition {
+    ZERO,
+    POSITIVE,
+    NEGATIVE,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Terminator {
+    JUMP(String),
+    BRANCH(Vec<Condition>, String), // taken when any condition holds, otherwise falls through
+    RETURN,
+    HALT,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Block {
+    pub label: Option<String>,
+    pub ops: Vec<Op>,
+    pub terminator: Option<Terminator>,
+}
+
+impl Block {
+    pub fn new(label: Option<String>) -> Self {
+        Block { label: label, ops: vec![], terminator: None }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Module {
+    pub blocks: Vec<Block>,
+    pub libraries: Vec<(String, String)>, // name and assembly source of each library the module uses
+    pub globals: Vec<(String, i32)>, // name and initial value of each global
+    pub variables: Vec<String>, // cells of function variables, parameters and arguments
+    pub tests: Vec<String>, // functions marked #[test]
+    pub asserts: Vec<(String, String)>, // label of the hlt each failing assertion stops at, and the assertion
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Variable(identifier) => { write!(f, "{}", identifier) }
+            Operand::Constant(value) => { write!(f, "#{}", value) }
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::LOAD(operand) => { write!(f, "load {}", operand) }
+            Op::STORE(identifier) => { write!(f, "store {}", identifier) }
+            Op::ADD(operand) => { write!(f, "add {}", operand) }
+            Op::SUB(operand) => { write!(f, "sub {}", operand) }
+            Op::CALL(identifier) => { write!(f, "call {}", identifier) }
+            Op::LINE(line) => { write!(f, "line {}", line) }
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Terminator::JUMP(label) => { write!(f, "jump {}", label) }
+            Terminator::BRANCH(conditions, label) => {
+                let conditions: Vec<String> = conditions.iter().map(|c| format!("{:?}", c).to_lowercase()).collect();
+                write!(f, "branch {} {}", conditions.join("|"), label)
+            }
+            Terminator::RETURN => { write!(f, "return") }
+            Terminator::HALT => { write!(f, "halt") }
+        }
+    }
+}
+
+// Textual form of a module, unlabelled blocks are shown by their index
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, block) in self.blocks.iter().enumerate() {
+            match &block.label {
+                Some(label) => { writeln!(f, "{}:", label)?; }
+                None => { writeln!(f, "<{}>:", index)?; }
+            }
+
+            for op in &block.ops {
+                writeln!(f, "    {}", op)?;
+            }
+
+            if let Some(terminator) = &block.terminator {
+                writeln!(f, "    {}", terminator)?;
+            }
+        }
+
+        for (name, _) in &self.libraries {
+            writeln!(f, "use {}", name)?;
+        }
+
+        for (identifier, value) in &self.globals {
+            writeln!(f, "global {} = {}", identifier, value)?;
+        }
+
+        for identifier in &self.variables {
+            writeln!(f, "variable {}", identifier)?;
+        }
+
+        for identifier in &self.tests {
+            writeln!(f, "test {}", identifier)?;
+        }
+
+        Ok(())
+    }
+}
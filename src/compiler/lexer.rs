@@ -1,4 +1,6 @@
 use std::collections::HashMap;
+use std::fmt;
+use crate::error::{Error, Result};
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum Token {
@@ -27,6 +29,9 @@ pub enum Token {
     RPAREN,
     LBRACE,
     RBRACE,
+    LBRACKET,
+    RBRACKET,
+    HASH,
 
     LET,
     FOR,
@@ -40,13 +45,71 @@ pub enum Token {
     ELSE,
 }
 
+impl Token {
+    // Name of the kind of token, the variant without its value
+    pub fn kind(&self) -> String {
+        match self {
+            Token::Identifier(_) => { String::from("IDENTIFIER") }
+            Token::String(_) => { String::from("STRING") }
+            Token::Number(_) => { String::from("NUMBER") }
+            _ => { format!("{:?}", self) }
+        }
+    }
+}
+
+// Source spelling of a token
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lexeme = match self {
+            Token::Identifier(identifier) => { identifier.as_str() }
+            Token::String(string) => { return write!(f, "{:?}", string) }
+            Token::Number(number) => { return write!(f, "{}", number) }
+
+            Token::SEMICOLON => { ";" }
+            Token::COMMA => { "," }
+            Token::EOF => { "" }
+            Token::ADD => { "+" }
+            Token::SUB => { "-" }
+            Token::NOT => { "!" }
+            Token::NE => { "!=" }
+            Token::EQ => { "=" }
+            Token::EE => { "==" }
+            Token::GT => { ">" }
+            Token::GTE => { ">=" }
+            Token::LT => { "<" }
+            Token::LTE => { "<=" }
+            Token::LPAREN => { "(" }
+            Token::RPAREN => { ")" }
+            Token::LBRACE => { "{" }
+            Token::RBRACE => { "}" }
+            Token::LBRACKET => { "[" }
+            Token::RBRACKET => { "]" }
+            Token::HASH => { "#" }
+            Token::LET => { "let" }
+            Token::FOR => { "for" }
+            Token::WHILE => { "while" }
+            Token::FN => { "fn" }
+            Token::USE => { "use" }
+            Token::RETURN => { "return" }
+            Token::HALT => { "halt" }
+            Token::IF => { "if" }
+            Token::ELIF => { "elif" }
+            Token::ELSE => { "else" }
+        };
+
+        write!(f, "{}", lexeme)
+    }
+}
+
 
 pub struct Lexer {
     program: Vec<char>,
     position: usize,
     read_position: usize,
     line_number: usize,
+    token_line: usize, // line the token being lexed starts on
     ch: char,
+    pub lines: Vec<usize>, // line of each lexed token
 }
 
 impl Lexer {
@@ -55,7 +118,7 @@ impl Lexer {
         let read_position: usize = 0;
         let ch = '\0';
 
-        Lexer { program: program, position: position, read_position: read_position, line_number: 1, ch: ch }
+        Lexer { program: program, position: position, read_position: read_position, line_number: 1, token_line: 1, ch: ch, lines: vec![] }
     }
 
     fn eat_char(&mut self) {
@@ -66,19 +129,20 @@ impl Lexer {
         }
 
         if self.ch == '\n' {
-            self.read_position += 1;
+            self.line_number += 1;
         }
 
         self.position = self.read_position;
         self.read_position += 1;
     }
 
-    pub fn lex(&mut self) -> Vec<Token> {
+    pub fn lex(&mut self) -> Result<Vec<Token>> {
         let mut tokens: Vec<Token> = vec![];
         self.eat_char();
 
         loop {
-            let token: Token = self.lex_token();
+            let token: Token = self.lex_token()?;
+            self.lines.push(self.token_line);
             if token == Token::EOF {
                 tokens.push(token);
                 break;
@@ -87,11 +151,12 @@ impl Lexer {
             tokens.push(token);
         }
 
-        tokens
+        Ok(tokens)
     }
 
-    fn lex_token(&mut self) -> Token {
+    fn lex_token(&mut self) -> Result<Token> {
         self.eat_whitespace();
+        self.token_line = self.line_number;
         let tok: Token;
 
         match self.ch {
@@ -108,20 +173,23 @@ impl Lexer {
             ')' => { tok = Token::RPAREN }
             '{' => { tok = Token::LBRACE }
             '}' => { tok = Token::RBRACE }
+            '[' => { tok = Token::LBRACKET }
+            ']' => { tok = Token::RBRACKET }
+            '#' => { tok = Token::HASH }
 
             ';' => { tok = Token::SEMICOLON }
             '\0' => { tok = Token::EOF }
             '"' => { tok = self.lex_string() }
-            '\'' => { tok = self.lex_char() }
+            '\'' => { tok = self.lex_char()? }
 
             '0'..='9' => { return self.lex_number() }
-            '_' |'A'..='z' => { return self.lex_identifier() }
+            '_' |'A'..='z' => { return Ok(self.lex_identifier()) }
 
-            _ => { panic!("Unexpected character found in lexer: {:?} line: {}", self.ch, self.line_number) }
+            _ => { return Err(Error::Lexer(format!("Unexpected character found in lexer: {:?} line: {}", self.ch, self.line_number))) }
         }
 
         self.eat_char();
-        tok
+        Ok(tok)
     }
 
     fn lex_multichar(&mut self, single: Token, double: (char, Token)) -> Token {
@@ -133,14 +201,15 @@ impl Lexer {
         return double.1
     }
 
-    fn lex_number(&mut self) -> Token {
+    fn lex_number(&mut self) -> Result<Token> {
         let position: usize = self.position;
         while self.position < self.program.len() && self.ch.is_numeric() {
             self.eat_char();
         }
 
         let numeral_str: String = self.program[position..self.position].to_vec().iter().collect();
-        Token::Number(numeral_str.parse::<i32>().unwrap())
+        numeral_str.parse::<i32>().map(Token::Number)
+            .map_err(|_| Error::Lexer(format!("number out of range: {} line: {}", numeral_str, self.line_number)))
     }
 
     fn lex_identifier(&mut self) -> Token {
@@ -182,7 +251,7 @@ impl Lexer {
         Token::String(self.program[position..self.position].to_vec().iter().collect())
     }
 
-    fn lex_char(&mut self) -> Token {
+    fn lex_char(&mut self) -> Result<Token> {
         self.eat_char();
         let mut ch = self.ch;
         self.eat_char();
@@ -191,10 +260,10 @@ impl Lexer {
             ch = escape.chars().next().unwrap();
             self.eat_char();
         } else if self.ch != '\'' {
-            panic!("Expected ' in char, got: {}", self.ch);
+            return Err(Error::Lexer(format!("Expected ' in char, got: {} line: {}", self.ch, self.line_number)));
         }
 
-        Token::Number(ch as i32)
+        Ok(Token::Number(ch as i32))
     }
 
     fn eat_whitespace(&mut self) {
@@ -211,7 +280,7 @@ mod tests {
     #[test]
     fn test_lex_number() {
         let mut l = Lexer::new(String::from("10 0 99 2147483647").chars().collect());
-        assert_eq!(l.lex(), vec![
+        assert_eq!(l.lex().unwrap(), vec![
             Token::Number(10),
             Token::Number(0),
             Token::Number(99),
@@ -223,7 +292,7 @@ mod tests {
     #[test]
     fn test_lex_multichar() {
         let mut l = Lexer::new(String::from("= == >= < <= > != ! =").chars().collect());
-        assert_eq!(l.lex(), vec![
+        assert_eq!(l.lex().unwrap(), vec![
             Token::EQ,
             Token::EE,
             Token::GTE,
@@ -240,7 +309,7 @@ mod tests {
     #[test]
     fn test_lex_identifier() {
         let mut l = Lexer::new(String::from("x y is_str let use if elif else").chars().collect());
-        assert_eq!(l.lex(), vec![
+        assert_eq!(l.lex().unwrap(), vec![
             Token::Identifier(String::from("x")),
             Token::Identifier(String::from("y")),
             Token::Identifier(String::from("is_str")),
@@ -253,10 +322,23 @@ mod tests {
         ])
     }
 
+    #[test]
+    fn test_lex_attribute() {
+        let mut l = Lexer::new(String::from("#[test] fn").chars().collect());
+        assert_eq!(l.lex().unwrap(), vec![
+            Token::HASH,
+            Token::LBRACKET,
+            Token::Identifier(String::from("test")),
+            Token::RBRACKET,
+            Token::FN,
+            Token::EOF,
+        ])
+    }
+
     #[test]
     fn test_lex_string() {
         let mut l = Lexer::new(String::from("\"Hello World\"").chars().collect());
-        assert_eq!(l.lex(), vec![
+        assert_eq!(l.lex().unwrap(), vec![
             Token::String(String::from("Hello World")),
             Token::EOF,
         ])
@@ -265,17 +347,64 @@ mod tests {
     #[test]
     fn test_lex_char() {
         let mut l = Lexer::new("'A'".to_string().chars().collect());
-        assert_eq!(l.lex(), vec![
+        assert_eq!(l.lex().unwrap(), vec![
             Token::Number(65),
             Token::EOF,
         ])
     }
 
+    #[test]
+    fn test_lex_newline() {
+        // the first char of a line is lexed like any other and the lines are counted
+        let mut l = Lexer::new(String::from("let\nx\ny").chars().collect());
+        assert_eq!(l.lex().unwrap(), vec![
+            Token::LET,
+            Token::Identifier(String::from("x")),
+            Token::Identifier(String::from("y")),
+            Token::EOF,
+        ]);
+        assert_eq!(l.line_number, 3);
+    }
+
     #[test]
     fn test_lex_empty() {
         let mut l = Lexer::new(String::from("").chars().collect());
-        assert_eq!(l.lex(), vec![
+        assert_eq!(l.lex().unwrap(), vec![
             Token::EOF,
         ])
     }
+
+    #[test]
+    fn test_lex_unexpected_character() {
+        let mut l = Lexer::new(String::from("let x = 1 * 2;").chars().collect());
+        assert_eq!(l.lex(), Err(Error::Lexer(String::from("Unexpected character found in lexer: '*' line: 1"))));
+    }
+
+    #[test]
+    fn test_token_display() {
+        let mut l = Lexer::new(String::from("let s = \"hi\"; x >= 10").chars().collect());
+        let tokens: Vec<String> = l.lex().unwrap().iter().map(|token| format!("{} {}", token.kind(), token)).collect();
+        assert_eq!(tokens, vec![
+            "LET let", "IDENTIFIER s", "EQ =", "STRING \"hi\"", "SEMICOLON ;", "IDENTIFIER x", "GTE >=", "NUMBER 10", "EOF ",
+        ]);
+    }
+
+    #[test]
+    fn test_lex_newlines() {
+        let mut l = Lexer::new(String::from("a\nb\n}\n'\n'").chars().collect());
+        assert_eq!(l.lex().unwrap(), vec![
+            Token::Identifier(String::from("a")),
+            Token::Identifier(String::from("b")),
+            Token::RBRACE,
+            Token::Number(10),
+            Token::EOF,
+        ])
+    }
+
+    #[test]
+    fn test_lex_lines() {
+        let mut l = Lexer::new(String::from("let x\n\n  = 1;\n").chars().collect());
+        l.lex().unwrap();
+        assert_eq!(l.lines, vec![1, 1, 3, 3, 3, 4]);
+    }
 }
@@ -1,4 +1,8 @@
 pub mod lexer;
 pub mod parser;
 pub mod node;
-pub mod compiler;
\ No newline at end of file
+pub mod codegen;
+pub mod compiler;
+pub mod folder;
+pub mod ir;
+pub mod peephole;
\ No newline at end of file
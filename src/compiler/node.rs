@@ -9,14 +9,205 @@ pub enum Node {
     INVOCATION(String, Box<Vec<Node>>),
     LIBRARY(String),
     FUNCTION(String, Vec<String>, Box<Node>),
+    ATTRIBUTE(String, Box<Node>), // name, function it is attached to
     RETURN(Box<Node>),
-    IF(Box<Vec<Node>>, Box<Node>), // list of conditionals, else 
+    IF(Box<Vec<Node>>, Box<Node>), // list of conditionals, else
     CONDITIONAL(Box<Node>, Box<Node>), // condition, consequence
     WHILE(Box<Node>, Box<Node>), // condition, consequence
     FOR(Box<Node>, Box<Node>, Box<Node>, Box<Node>), // declaration (before loop), condition (during), increment (after consequence), consequence
     HALT(),
+    LINE(usize, Box<Node>), // source line the node starts on, only in trees parsed with lines
 
     IDENTIFIER(String),
     NUMBER(i32),
     STRING(String),
-}
\ No newline at end of file
+}
+
+impl Node {
+    // Renders the tree one node per line, children indented below their parent
+    pub fn tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(&mut out, 0);
+        out
+    }
+
+    fn write_tree(&self, out: &mut String, depth: usize) {
+        let (label, children): (String, Vec<&Node>) = match self {
+            Node::BLOCK(statements) => { (String::from("BLOCK"), statements.iter().collect()) }
+            Node::DECLARATION(identifier, expression) => { (format!("DECLARATION {}", identifier), vec![expression]) }
+            Node::ASSIGNMENT(identifier, expression) => { (format!("ASSIGNMENT {}", identifier), vec![expression]) }
+            Node::INFIX(lhs, op, rhs) => { (format!("INFIX {}", op), vec![lhs, rhs]) }
+            Node::INVOCATION(identifier, arguments) => { (format!("INVOCATION {}", identifier), arguments.iter().collect()) }
+            Node::LIBRARY(library) => { (format!("LIBRARY {}", library), vec![]) }
+            Node::FUNCTION(identifier, parameters, body) => { (format!("FUNCTION {}({})", identifier, parameters.join(", ")), vec![body]) }
+            Node::ATTRIBUTE(name, function) => { (format!("ATTRIBUTE {}", name), vec![function]) }
+            Node::RETURN(expression) => { (String::from("RETURN"), vec![expression]) }
+            Node::IF(conditionals, alternative) => {
+                let mut children: Vec<&Node> = conditionals.iter().collect();
+                children.push(alternative);
+                (String::from("IF"), children)
+            }
+            Node::CONDITIONAL(condition, consequence) => { (String::from("CONDITIONAL"), vec![condition, consequence]) }
+            Node::WHILE(condition, consequence) => { (String::from("WHILE"), vec![condition, consequence]) }
+            Node::FOR(declaration, condition, increment, consequence) => {
+                (String::from("FOR"), vec![declaration, condition, increment, consequence])
+            }
+            Node::HALT() => { (String::from("HALT"), vec![]) }
+            Node::LINE(line, node) => { (format!("LINE {}", line), vec![node]) }
+
+            Node::IDENTIFIER(identifier) => { (format!("IDENTIFIER {}", identifier), vec![]) }
+            Node::NUMBER(value) => { (format!("NUMBER {}", value), vec![]) }
+            Node::STRING(value) => { (format!("STRING {:?}", value), vec![]) }
+        };
+
+        *out += &format!("{}{}\n", "  ".repeat(depth), label);
+        for child in children {
+            child.write_tree(out, depth + 1);
+        }
+    }
+
+    // Serializes the tree as JSON, every node an object with its variant under "type"
+    pub fn to_json(&self) -> String {
+        let fields: Vec<(&str, String)> = match self {
+            Node::BLOCK(statements) => { vec![("statements", json_list(statements))] }
+            Node::DECLARATION(identifier, expression) | Node::ASSIGNMENT(identifier, expression) => {
+                vec![("identifier", json_string(identifier)), ("expression", expression.to_json())]
+            }
+            Node::INFIX(lhs, op, rhs) => {
+                vec![("lhs", lhs.to_json()), ("op", json_string(&op.to_string())), ("rhs", rhs.to_json())]
+            }
+            Node::INVOCATION(identifier, arguments) => {
+                vec![("identifier", json_string(identifier)), ("arguments", json_list(arguments))]
+            }
+            Node::LIBRARY(library) => { vec![("name", json_string(library))] }
+            Node::FUNCTION(identifier, parameters, body) => {
+                let parameters: Vec<String> = parameters.iter().map(|p| json_string(p)).collect();
+                vec![("identifier", json_string(identifier)), ("parameters", format!("[{}]", parameters.join(","))), ("body", body.to_json())]
+            }
+            Node::ATTRIBUTE(name, function) => { vec![("name", json_string(name)), ("function", function.to_json())] }
+            Node::RETURN(expression) => { vec![("expression", expression.to_json())] }
+            Node::IF(conditionals, alternative) => {
+                vec![("conditionals", json_list(conditionals)), ("alternative", alternative.to_json())]
+            }
+            Node::CONDITIONAL(condition, consequence) | Node::WHILE(condition, consequence) => {
+                vec![("condition", condition.to_json()), ("consequence", consequence.to_json())]
+            }
+            Node::FOR(declaration, condition, increment, consequence) => {
+                vec![("declaration", declaration.to_json()), ("condition", condition.to_json()),
+                    ("increment", increment.to_json()), ("consequence", consequence.to_json())]
+            }
+            Node::HALT() => { vec![] }
+            Node::LINE(line, node) => { vec![("line", line.to_string()), ("node", node.to_json())] }
+
+            Node::IDENTIFIER(identifier) => { vec![("name", json_string(identifier))] }
+            Node::NUMBER(value) => { vec![("value", value.to_string())] }
+            Node::STRING(value) => { vec![("value", json_string(value))] }
+        };
+
+        let mut members = vec![format!("\"type\":{}", json_string(self.kind()))];
+        members.extend(fields.iter().map(|(name, value)| format!("\"{}\":{}", name, value)));
+        format!("{{{}}}", members.join(","))
+    }
+
+    // Source spelling of an expression, used to describe it in messages
+    pub fn source(&self) -> String {
+        match self {
+            Node::INFIX(lhs, op, rhs) => {
+                match **rhs {
+                    Node::INFIX(_, _, _) => { format!("{} {} ({})", lhs.source(), op, rhs.source()) }
+                    _ => { format!("{} {} {}", lhs.source(), op, rhs.source()) }
+                }
+            }
+            Node::INVOCATION(identifier, arguments) => {
+                let arguments: Vec<String> = arguments.iter().map(|argument| argument.source()).collect();
+                format!("{}({})", identifier, arguments.join(", "))
+            }
+            Node::IDENTIFIER(identifier) => { identifier.clone() }
+            Node::NUMBER(value) => { value.to_string() }
+            Node::STRING(value) => { format!("{:?}", value) }
+            Node::LINE(_, node) => { node.source() }
+            _ => { self.kind().to_string() }
+        }
+    }
+
+    pub fn kind(&self) -> &str {
+        match self {
+            Node::BLOCK(_) => { "BLOCK" }
+            Node::DECLARATION(_, _) => { "DECLARATION" }
+            Node::ASSIGNMENT(_, _) => { "ASSIGNMENT" }
+            Node::INFIX(_, _, _) => { "INFIX" }
+            Node::INVOCATION(_, _) => { "INVOCATION" }
+            Node::LIBRARY(_) => { "LIBRARY" }
+            Node::FUNCTION(_, _, _) => { "FUNCTION" }
+            Node::ATTRIBUTE(_, _) => { "ATTRIBUTE" }
+            Node::RETURN(_) => { "RETURN" }
+            Node::IF(_, _) => { "IF" }
+            Node::CONDITIONAL(_, _) => { "CONDITIONAL" }
+            Node::WHILE(_, _) => { "WHILE" }
+            Node::FOR(_, _, _, _) => { "FOR" }
+            Node::HALT() => { "HALT" }
+            Node::LINE(_, _) => { "LINE" }
+            Node::IDENTIFIER(_) => { "IDENTIFIER" }
+            Node::NUMBER(_) => { "NUMBER" }
+            Node::STRING(_) => { "STRING" }
+        }
+    }
+}
+
+fn json_list(nodes: &[Node]) -> String {
+    let items: Vec<String> = nodes.iter().map(|node| node.to_json()).collect();
+    format!("[{}]", items.join(","))
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::from("\"");
+    for ch in value.chars() {
+        match ch {
+            '"' => { out += "\\\"" }
+            '\\' => { out += "\\\\" }
+            '\n' => { out += "\\n" }
+            '\r' => { out += "\\r" }
+            '\t' => { out += "\\t" }
+            c if (c as u32) < 0x20 => { out += &format!("\\u{:04x}", c as u32) }
+            c => { out.push(c) }
+        }
+    }
+
+    out + "\""
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn declaration() -> Node {
+        Node::DECLARATION(String::from("x"), Box::new(Node::INFIX(
+            Box::new(Node::NUMBER(3)), Token::SUB, Box::new(Node::IDENTIFIER(String::from("y"))),
+        )))
+    }
+
+    #[test]
+    fn test_tree() {
+        let ast = Node::BLOCK(Box::new(vec![declaration(), Node::HALT()]));
+        assert_eq!(ast.tree(), "BLOCK\n  DECLARATION x\n    INFIX -\n      NUMBER 3\n      IDENTIFIER y\n  HALT\n");
+    }
+
+    #[test]
+    fn test_source() {
+        let invocation = Node::INVOCATION(String::from("f"), Box::new(vec![Node::NUMBER(1), Node::IDENTIFIER(String::from("x"))]));
+        assert_eq!(Node::INFIX(Box::new(invocation), Token::EE, Box::new(Node::NUMBER(2))).source(), "f(1, x) == 2");
+        assert_eq!(Node::INFIX(Box::new(Node::NUMBER(1)), Token::SUB, Box::new(declaration())).source(), "1 - DECLARATION");
+        assert_eq!(Node::INFIX(Box::new(Node::NUMBER(1)), Token::SUB, Box::new(Node::INFIX(
+            Box::new(Node::NUMBER(2)), Token::ADD, Box::new(Node::NUMBER(3)),
+        ))).source(), "1 - (2 + 3)");
+    }
+
+    #[test]
+    fn test_to_json() {
+        assert_eq!(declaration().to_json(), concat!(
+            r#"{"type":"DECLARATION","identifier":"x","expression":"#,
+            r#"{"type":"INFIX","lhs":{"type":"NUMBER","value":3},"op":"-","rhs":{"type":"IDENTIFIER","name":"y"}}}"#,
+        ));
+        assert_eq!(Node::STRING(String::from("a\"b\n")).to_json(), r#"{"type":"STRING","value":"a\"b\n"}"#);
+    }
+}
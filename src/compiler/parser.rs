@@ -2,9 +2,11 @@ use std::collections::HashMap;
 
 use crate::compiler::lexer::Token;
 use crate::compiler::node::Node;
+use crate::error::{Error, Result};
 
 pub struct Parser {
     tokens: Vec<Token>,
+    lines: Vec<usize>, // line of each token, empty when not tracked
     position: usize,
     token: Token,
     next_token: Token,
@@ -12,9 +14,26 @@ pub struct Parser {
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
+        Parser::with_lines(tokens, vec![])
+    }
+
+    // Parses with the line of each token, statements are then wrapped in Node::LINE for the compiler's debug info
+    pub fn with_lines(tokens: Vec<Token>, lines: Vec<usize>) -> Self {
         let tok = tokens[0].clone();
         let next_tok = if tokens.len() > 1 { tokens[1].clone() } else { Token::EOF };
-        Parser { tokens: tokens, position: 0, token: tok, next_token: next_tok }
+        Parser { tokens: tokens, lines: lines, position: 0, token: tok, next_token: next_tok }
+    }
+
+    // Line of the current token
+    fn line(&self) -> Option<usize> {
+        self.lines.get(self.position).copied()
+    }
+
+    fn at_line(&self, line: Option<usize>, node: Node) -> Node {
+        match line {
+            Some(line) => { Node::LINE(line, Box::new(node)) }
+            None => { node }
+        }
     }
 
     fn eat(&mut self) {
@@ -23,301 +42,332 @@ impl Parser {
         self.next_token = if self.position + 1 >= self.tokens.len() { Token::EOF } else { self.tokens[self.position + 1].clone() };
     }
 
-    fn peek_error(&mut self, t: Token) {
+    fn peek_error(&mut self, t: Token) -> Result<()> {
         if std::mem::discriminant(&self.next_token) != std::mem::discriminant(&t) {
-            panic!("expected eat token to be {:?}, got {:?}", t, self.next_token);
+            return Err(Error::Parser(format!("expected eat token to be {:?}, got {:?}", t, self.next_token)));
         }
 
         self.eat();
+        Ok(())
     }
 
-    fn eat_error(&mut self, t: Token) {
+    fn eat_error(&mut self, t: Token) -> Result<()> {
         if std::mem::discriminant(&self.token) != std::mem::discriminant(&t) {
-            panic!("expected eat token to be {:?}, got {:?}", t, self.token);
+            return Err(Error::Parser(format!("expected eat token to be {:?}, got {:?}", t, self.token)));
         }
         self.eat();
+        Ok(())
     }
 
-    fn is_error(&mut self, t: Token) {
+    fn is_error(&mut self, t: Token) -> Result<()> {
         if std::mem::discriminant(&self.token) != std::mem::discriminant(&t) {
-            panic!("expected eat token to be {:?}, got {:?}", t, self.token);
+            return Err(Error::Parser(format!("expected eat token to be {:?}, got {:?}", t, self.token)));
         }
+        Ok(())
     }
 
-    pub fn parse(&mut self) -> Node {
+    fn identifier(&self, context: &str) -> Result<String> {
+        match &self.token {
+            Token::Identifier(id) => { Ok(id.clone()) }
+            _ => { Err(Error::Parser(format!("{}, expected type IDENTIFIER, got: {:?}", context, self.token))) }
+        }
+    }
+
+    pub fn parse(&mut self) -> Result<Node> {
         let mut statements = vec![];
 
         while self.token != Token::EOF {
-            let statement = self.parse_statement();
+            let statement = self.parse_statement()?;
             match statement {
                 Node::FUNCTION(_, _, _) => {}
+                Node::ATTRIBUTE(_, _) => {}
                 Node::IF(_, _) => {}
                 Node::WHILE(_, _) => {}
                 Node::FOR(_, _, _, _) => {}
-                _ => { self.eat_error(Token::SEMICOLON) } 
+                _ => { self.eat_error(Token::SEMICOLON)? } 
             }
 
             statements.push(statement);
         }
 
-        Node::BLOCK(Box::new(statements))
+        Ok(Node::BLOCK(Box::new(statements)))
     }
 
-    fn parse_statement(&mut self) -> Node {
+    fn parse_statement(&mut self) -> Result<Node> {
         match &self.token {
             Token::LET => { self.parse_declaration() }
             Token::USE => { self.parse_use() }
             Token::FN  => { self.parse_function() }
+            Token::HASH => { self.parse_attribute() }
             Token::RETURN => { self.parse_return() }
             Token::IF => { self.parse_if() }
             Token::WHILE => { self.parse_while() }
             Token::FOR => { self.parse_for() }
-            Token::Identifier(id) =>  { 
-                if self.next_token == Token::EQ {
-                    self.parse_assignment(id.clone())
-                } else {
-                    self.parse_expression(0)
-                }
-            }
+            Token::Identifier(id) if self.next_token == Token::EQ => { self.parse_assignment(id.clone()) }
             Token::HALT => { 
                 self.eat();
-                Node::HALT()
+                Ok(Node::HALT())
              }
             _ => { self.parse_expression(0) }
             // _ => { panic!("Invalid Token: {:?} to begin a statement", self.token) }
         }
     }
 
-    fn parse_expression(&mut self, rbp: i32) -> Node {
-        let mut lhs = self.parse_atom();
+    fn parse_expression(&mut self, rbp: i32) -> Result<Node> {
+        let mut lhs = self.parse_atom()?;
         self.eat();
 
         let mut peek_rbp = self.get_preference(self.token.clone());
 
         while self.next_token != Token::EOF && peek_rbp >= rbp {
-            lhs = self.parse_infix(lhs, self.token.clone());
+            lhs = self.parse_infix(lhs, self.token.clone())?;
             peek_rbp = self.get_preference(self.token.clone());
         }
 
-        lhs
+        Ok(lhs)
     }
 
-    fn parse_infix(&mut self, lhs: Node, op: Token) -> Node {
-        if !vec![Token::ADD, Token::SUB, Token::EE, Token::NE, Token::LT, Token::GT, Token::GTE, Token::LTE].contains(&op) {
-            panic!("SyntaxError: unsupported infix operator, got: {:?}", op);
+    fn parse_infix(&mut self, lhs: Node, op: Token) -> Result<Node> {
+        if ![Token::ADD, Token::SUB, Token::EE, Token::NE, Token::LT, Token::GT, Token::GTE, Token::LTE].contains(&op) {
+            return Err(Error::Parser(format!("unsupported infix operator, got: {:?}", op)));
         }
 
         self.eat();
-        let rhs = self.parse_expression(self.get_preference(op.clone()) + 1);
+        let rhs = self.parse_expression(self.get_preference(op.clone()) + 1)?;
 
-        return Node::INFIX(
+        return Ok(Node::INFIX(
             Box::new(lhs), 
             op,
             Box::new(rhs)
-        )
+        ))
     }
 
 
-    fn parse_atom(&mut self) -> Node {
+    fn parse_atom(&mut self) -> Result<Node> {
         let node: Node;
         match &self.token {
-            Token::Number(value) => { node = Node::NUMBER(value.clone()); }
+            Token::Number(value) => { node = Node::NUMBER(*value); }
             Token::String(value) => { node = Node::STRING(value.clone()); }
             Token::Identifier(id) => { 
                 match self.next_token {
-                    Token::LPAREN => { node = self.parse_invocation(id.clone()) } 
+                    Token::LPAREN => { node = self.parse_invocation(id.clone())? } 
                     _ => { node = Node::IDENTIFIER(id.clone())}
                 }
             }
             Token::LPAREN => {
                 self.eat();
-                node = self.parse_expression(0);
-                self.is_error(Token::RPAREN);
+                node = self.parse_expression(0)?;
+                self.is_error(Token::RPAREN)?;
             }
 
-            _ => { panic!("Unexpected token in expression: {:?}", self.token); }
+            _ => { return Err(Error::Parser(format!("Unexpected token in expression: {:?}", self.token))) }
         }
 
-        node
+        Ok(node)
     }
 
 
-    fn parse_use(&mut self) -> Node {
-        self.peek_error(Token::Identifier(String::from("")));
-        let identifier_str = if let Token::Identifier(identifier) = self.token.clone() { identifier } else { panic!("DECLARATION, expected type IDENTIFIER following LET, got: {:?}", self.token) };
+    fn parse_use(&mut self) -> Result<Node> {
+        self.peek_error(Token::Identifier(String::from("")))?;
+        let identifier_str = self.identifier("USE")?;
         self.eat();
 
-        Node::LIBRARY(identifier_str.clone())
+        Ok(Node::LIBRARY(identifier_str.clone()))
     }
 
 
-    fn parse_invocation(&mut self, identifier: String) -> Node {
+    fn parse_invocation(&mut self, identifier: String) -> Result<Node> {
         self.eat();
 
         let mut args: Vec<Node> = vec![];
 
         self.eat();
         while self.token != Token::RPAREN && self.token != Token::EOF {
-            let expr = self.parse_expression(0);
+            let expr = self.parse_expression(0)?;
             args.push(expr);
 
             if self.token != Token::RPAREN && self.token != Token::COMMA {
-                panic!("SyntaxError: expected '(' or ',' following function parameter, got: {:?}", self.token);
+                return Err(Error::Parser(format!("expected '(' or ',' following function parameter, got: {:?}", self.token)));
             }
 
             if self.token == Token::COMMA { self.eat(); }
         }
 
-        Node::INVOCATION(
+        Ok(Node::INVOCATION(
             identifier, 
             Box::new(args)
-        )
+        ))
     }
 
 
-    fn parse_return(&mut self) -> Node {
+    fn parse_return(&mut self) -> Result<Node> {
         self.eat();
         let mut expression = Node::NUMBER(0);
         if self.token != Token::SEMICOLON {
-            expression = self.parse_expression(0);
+            expression = self.parse_expression(0)?;
         }
-        Node::RETURN(Box::new(expression))
+        Ok(Node::RETURN(Box::new(expression)))
     }
 
 
-    fn parse_declaration(&mut self) -> Node {
-        self.peek_error(Token::Identifier(String::from("")));
-
-        // let identifier_str = if let Token::Identifier(identifier) = &self.token { identifier } else { panic!("DECLARATION, expected type IDENTIFIER following LET, got: {:?}", self.token) };
-        let identifier_str = if let Token::Identifier(id) = self.token.clone() { id } else { panic!("") };
+    fn parse_declaration(&mut self) -> Result<Node> {
+        self.peek_error(Token::Identifier(String::from("")))?;
+        let identifier_str = self.identifier("DECLARATION")?;
 
         if self.next_token == Token::SEMICOLON {
             self.eat();
-            return Node::DECLARATION(identifier_str.clone(), Box::new(Node::NUMBER(0)));
+            return Ok(Node::DECLARATION(identifier_str.clone(), Box::new(Node::NUMBER(0))));
         }
 
-        self.peek_error(Token::EQ);
+        self.peek_error(Token::EQ)?;
         self.eat(); // positing to expression
 
-        let expression = self.parse_expression(0);
+        let expression = self.parse_expression(0)?;
 
-        Node::DECLARATION(
+        Ok(Node::DECLARATION(
             identifier_str.clone(),
             Box::new(expression),
-        )
+        ))
     }
 
-    fn parse_assignment(&mut self, identifier: String) -> Node {
+    fn parse_assignment(&mut self, identifier: String) -> Result<Node> {
         self.eat();
-        self.eat_error(Token::EQ);
-        let expr = self.parse_expression(0);
+        self.eat_error(Token::EQ)?;
+        let expr = self.parse_expression(0)?;
 
-        Node::ASSIGNMENT(identifier, Box::new(expr))
+        Ok(Node::ASSIGNMENT(identifier, Box::new(expr)))
     }
 
 
-    fn parse_function(&mut self) -> Node {
-        self.peek_error(Token::Identifier("".to_string()));
-        let identifier: String = if let Token::Identifier(id) = self.token.clone() { id } else { panic!("could not extract identifier from token FUNCTION") };
+    fn parse_function(&mut self) -> Result<Node> {
+        let line = self.line();
+        self.peek_error(Token::Identifier("".to_string()))?;
+        let identifier: String = self.identifier("FUNCTION")?;
 
-        self.peek_error(Token::LPAREN);
+        self.peek_error(Token::LPAREN)?;
         self.eat();
 
         let mut args: Vec<String> = vec![];
         while self.token != Token::EOF &&  self.token != Token::RPAREN {
-            let arg: String = if let Token::Identifier(id) = self.token.clone() { id } else { panic!("could not extract identifier from token FUNCTION") };
+            let arg: String = self.identifier("FUNCTION argument")?;
             self.eat();
 
             if self.token != Token::COMMA && self.token != Token::RPAREN {
-                panic!("Unexpected token encountered when parsing arguments, expected ',' or ')', got: {:?}", self.token);
+                return Err(Error::Parser(format!("Unexpected token encountered when parsing arguments, expected ',' or ')', got: {:?}", self.token)));
             }
             
             if self.token == Token::COMMA { self.eat(); }
             args.push(arg);
         }
 
-        self.peek_error(Token::LBRACE);
+        self.peek_error(Token::LBRACE)?;
+        self.eat();
+        let block = self.parse_block()?;
+        self.is_error(Token::RBRACE)?;
         self.eat();
-        let block = self.parse_block();
+
+        // the parameters are stored on entry, on the line of the fn
+        Ok(Node::FUNCTION(identifier, args, Box::new(self.at_line(line, block))))
+    }
+
+
+    // #[name] before a function
+    fn parse_attribute(&mut self) -> Result<Node> {
+        self.peek_error(Token::LBRACKET)?;
+        self.peek_error(Token::Identifier("".to_string()))?;
+        let name: String = self.identifier("ATTRIBUTE")?;
+
+        self.peek_error(Token::RBRACKET)?;
         self.eat();
+        self.is_error(Token::FN)?;
+        let function = self.parse_function()?;
 
-        Node::FUNCTION(identifier, args, Box::new(block))
+        Ok(Node::ATTRIBUTE(name, Box::new(function)))
     }
 
 
-    fn parse_block(&mut self) -> Node {
+    fn parse_block(&mut self) -> Result<Node> {
         let mut statements = vec![];
 
         while self.token != Token::EOF && self.token != Token::RBRACE {
-            let statement = self.parse_statement();
+            let line = self.line();
+            let statement = self.parse_statement()?;
             match statement {
                 Node::FUNCTION(_, _, _) => {}
+                Node::ATTRIBUTE(_, _) => {}
                 Node::IF(_, _) => {}
                 Node::WHILE(_, _) => {}
                 Node::FOR(_,_,_, _) => {}
-                _ => { self.eat_error(Token::SEMICOLON) } 
+                _ => { self.eat_error(Token::SEMICOLON)? } 
             }
 
-            statements.push(statement);
+            statements.push(self.at_line(line, statement));
         }
 
-        Node::BLOCK(Box::new(statements))
+        Ok(Node::BLOCK(Box::new(statements)))
     }
 
 
-    fn parse_if(&mut self) -> Node {
+    fn parse_if(&mut self) -> Result<Node> {
         let mut conditionals: Vec<Node> = vec![];
 
         while self.token == Token::IF || self.token == Token::ELIF {
+            let line = self.line();
             self.eat();
 
-            let condition = self.parse_expression(0);
-            self.eat_error(Token::LBRACE);
-            let consequence = self.parse_block();
-            self.eat_error(Token::RBRACE);
+            let condition = self.parse_expression(0)?;
+            let condition = self.at_line(line, condition);
+            self.eat_error(Token::LBRACE)?;
+            let consequence = self.parse_block()?;
+            self.eat_error(Token::RBRACE)?;
 
             conditionals.push(Node::CONDITIONAL(Box::new(condition), Box::new(consequence)));
         }
 
-        let mut else_block = Node::BLOCK(Box::new(vec![]));
+        let mut else_block = Node::BLOCK(Box::default());
         if self.token == Token::ELSE {
-            self.peek_error(Token::LBRACE); 
+            self.peek_error(Token::LBRACE)?; 
             self.eat();
-            else_block = self.parse_block();
-            self.eat_error(Token::RBRACE);
+            else_block = self.parse_block()?;
+            self.eat_error(Token::RBRACE)?;
         }
 
-        Node::IF(Box::new(conditionals), Box::new(else_block))
+        Ok(Node::IF(Box::new(conditionals), Box::new(else_block)))
     }
 
 
-    fn parse_while(&mut self) -> Node {
+    fn parse_while(&mut self) -> Result<Node> {
+        let line = self.line();
         self.eat();
-        let condition = self.parse_expression(0);
-        self.eat_error(Token::LBRACE);
-        let consequence = self.parse_block();
-        self.eat_error(Token::RBRACE);
+        let condition = self.parse_expression(0)?;
+        let condition = self.at_line(line, condition);
+        self.eat_error(Token::LBRACE)?;
+        let consequence = self.parse_block()?;
+        self.eat_error(Token::RBRACE)?;
 
-        Node::WHILE(Box::new(condition), Box::new(consequence))
+        Ok(Node::WHILE(Box::new(condition), Box::new(consequence)))
     }
 
-    fn parse_for(&mut self) -> Node {
+    fn parse_for(&mut self) -> Result<Node> {
+        let line = self.line();
         self.eat();
 
-        let declaraion = self.parse_statement();
-        self.eat_error(Token::SEMICOLON);
+        let declaraion = self.parse_statement()?;
+        self.eat_error(Token::SEMICOLON)?;
 
-        let condition = self.parse_expression(0);
-        self.eat_error(Token::SEMICOLON);
+        let condition = self.parse_expression(0)?;
+        self.eat_error(Token::SEMICOLON)?;
 
-        let increment = self.parse_statement();
-        self.eat_error(Token::SEMICOLON);
-        self.eat_error(Token::LBRACE);
+        // the increment runs after the consequence, on the line of the for
+        let increment = self.parse_statement()?;
+        let increment = self.at_line(line, increment);
+        self.eat_error(Token::SEMICOLON)?;
+        self.eat_error(Token::LBRACE)?;
 
-        let consequence = self.parse_block();
-        self.eat_error(Token::RBRACE);
+        let consequence = self.parse_block()?;
+        self.eat_error(Token::RBRACE)?;
 
-        Node::FOR(Box::new(declaraion), Box::new(condition), Box::new(increment), Box::new(consequence))
+        Ok(Node::FOR(Box::new(declaraion), Box::new(condition), Box::new(increment), Box::new(consequence)))
     }
 
 
@@ -338,7 +388,7 @@ impl Parser {
         ].iter().cloned().collect();
 
         if preferences.contains_key(&t) {
-            preferences.get(&t).unwrap().clone()
+            *preferences.get(&t).unwrap()
         } else {
             -1
         }
@@ -377,7 +427,7 @@ mod tests {
             Token::EOF,
         ]);
 
-        assert_eq!(p.parse(), Node::BLOCK(Box::new(vec![
+        assert_eq!(p.parse().unwrap(), Node::BLOCK(Box::new(vec![
             Node::INVOCATION(
                 "print".to_string(), 
                 Box::new(vec![
@@ -426,7 +476,7 @@ mod tests {
             Token::EOF,
         ]);
 
-        assert_eq!(p.parse(), Node::BLOCK(Box::new(vec![
+        assert_eq!(p.parse().unwrap(), Node::BLOCK(Box::new(vec![
             Node::DECLARATION(
                 String::from("x"), 
                 Box::new(Node::NUMBER(1)),
@@ -451,11 +501,49 @@ mod tests {
             Token::EOF,
         ]);
 
-        assert_eq!(p.parse(), Node::BLOCK(Box::new(vec![
+        assert_eq!(p.parse().unwrap(), Node::BLOCK(Box::new(vec![
             Node::LIBRARY("std".to_string())
         ])))
     }
 
+    #[test]
+    fn test_parse_function_unclosed() {
+        let mut p = Parser::new(vec![
+            Token::FN,
+            Token::Identifier(String::from("f")),
+            Token::LPAREN,
+            Token::RPAREN,
+            Token::LBRACE,
+            Token::EOF,
+        ]);
+
+        assert_eq!(p.parse(), Err(Error::Parser(String::from("expected eat token to be RBRACE, got EOF"))));
+    }
+
+    #[test]
+    fn test_parse_attribute() {
+        let mut p = Parser::new(vec![
+            Token::HASH,
+            Token::LBRACKET,
+            Token::Identifier(String::from("test")),
+            Token::RBRACKET,
+            Token::FN,
+            Token::Identifier(String::from("f")),
+            Token::LPAREN,
+            Token::RPAREN,
+            Token::LBRACE,
+            Token::RBRACE,
+            Token::EOF,
+        ]);
+
+        assert_eq!(p.parse().unwrap(), Node::BLOCK(Box::new(vec![
+            Node::ATTRIBUTE(String::from("test"), Box::new(Node::FUNCTION(String::from("f"), vec![], Box::new(Node::BLOCK(Box::default())))))
+        ])));
+
+        let mut p = Parser::new(vec![Token::HASH, Token::LBRACKET, Token::Identifier(String::from("test")), Token::RBRACKET, Token::LET, Token::EOF]);
+        assert!(p.parse().is_err());
+    }
+
     #[test]
     fn test_parse_parenthesis() {
         let mut p = Parser::new(vec![
@@ -487,7 +575,7 @@ mod tests {
             Token::EOF,
         ]);
 
-        assert_eq!(p.parse(), Node::BLOCK(Box::new(vec![
+        assert_eq!(p.parse().unwrap(), Node::BLOCK(Box::new(vec![
             Node::INFIX(
                 Box::new(Node::NUMBER(3)), 
                 Token::ADD,
@@ -512,4 +600,17 @@ mod tests {
             )
         ])))
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_missing_semicolon() {
+        let mut p = Parser::new(vec![
+            Token::LET,
+            Token::Identifier(String::from("x")),
+            Token::EQ,
+            Token::Number(1),
+            Token::EOF,
+        ]);
+
+        assert_eq!(p.parse(), Err(Error::Parser(String::from("expected eat token to be SEMICOLON, got EOF"))));
+    }
+}
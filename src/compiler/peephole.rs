@@ -0,0 +1,171 @@
+use crate::assembler::lexer::{self, Token};
+use crate::assembler::parser::{self, Instruction};
+use crate::error::Result;
+
+// Peephole optimizer over compiled assembly. The assembly is parsed into its instructions with the labels attached
+// to each of them, patterns of neighbouring instructions are rewritten until none apply, and the result is rendered
+// back to assembly. A labelled instruction may be reached by a branch, so it is never removed on account of the
+// instruction before it.
+pub struct Peephole {
+    instructions: Vec<Instruction>,
+    labels: Vec<Vec<String>>, // labels of each instruction, the last entry holds labels past the end
+    pub lines: Vec<Option<usize>>, // source line of each instruction, removed along with it
+}
+
+impl Peephole {
+    pub fn new(asm: &str) -> Result<Self> {
+        let mut l = lexer::Lexer::new(asm.chars().collect());
+        let mut p = parser::Parser::new(l.lex()?);
+        let (instructions, symbol_table) = p.parse()?;
+
+        let mut labels: Vec<Vec<String>> = vec![vec![]; instructions.len() + 1];
+        for (label, address) in symbol_table {
+            labels[address as usize].push(label);
+        }
+
+        for names in labels.iter_mut() {
+            names.sort();
+        }
+
+        let lines = vec![None; instructions.len()];
+        Ok(Peephole { instructions: instructions, labels: labels, lines: lines })
+    }
+
+    // Keeps the source line of each instruction, instructions past the lines given have none
+    pub fn with_lines(asm: &str, lines: &[Option<usize>]) -> Result<Self> {
+        let mut p = Peephole::new(asm)?;
+        for (line, given) in p.lines.iter_mut().zip(lines) {
+            *line = *given;
+        }
+
+        Ok(p)
+    }
+
+    pub fn optimize(&mut self) {
+        while self.pass() {}
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (instruction, labels) in self.instructions.iter().zip(&self.labels) {
+            match labels.split_last() {
+                Some((last, rest)) => {
+                    for label in rest {
+                        out += &format!("{label}\n");
+                    }
+
+                    out += &format!("{last} {instruction}\n");
+                }
+                None => { out += &format!("{instruction}\n"); }
+            }
+        }
+
+        for label in &self.labels[self.instructions.len()] {
+            out += &format!("{label}\n");
+        }
+
+        out
+    }
+
+    // Applies the first matching rewrite, returns whether the program changed
+    fn pass(&mut self) -> bool {
+        for i in 0..self.instructions.len() {
+            let next = self.instructions.get(i + 1);
+            let next_labelled = !self.labels[i + 1].is_empty();
+
+            let removed = match (&self.instructions[i], next) {
+                // branch to the next instruction
+                (Instruction::BRA(Token::Label(target)) | Instruction::BRZ(Token::Label(target)) |
+                 Instruction::BGT(Token::Label(target)) | Instruction::BLT(Token::Label(target)), _) if self.labels[i + 1].contains(target) => { Some(i) }
+
+                // the accumulator already holds the stored value
+                (Instruction::STA(stored), Some(Instruction::LDA(loaded))) if stored == loaded && !next_labelled => { Some(i + 1) }
+                // storing the value just loaded from, or just stored to, the same cell
+                (Instruction::LDA(loaded), Some(Instruction::STA(stored))) if stored == loaded && !next_labelled => { Some(i + 1) }
+                (Instruction::STA(first), Some(Instruction::STA(second))) if first == second && !next_labelled => { Some(i + 1) }
+                // a load overwritten by the next load
+                (Instruction::LDA(_), Some(Instruction::LDA(_))) => { Some(i) }
+
+                // unreachable code, data cells are kept as they may be addressed
+                (Instruction::BRA(_) | Instruction::RET | Instruction::HLT, Some(instruction))
+                    if !next_labelled && !matches!(instruction, Instruction::DAT(_)) => { Some(i + 1) }
+
+                _ => { None }
+            };
+
+            if let Some(index) = removed {
+                self.remove(index);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    // Removes an instruction, its labels move to the instruction after it
+    fn remove(&mut self, index: usize) {
+        let labels = self.labels.remove(index);
+        self.labels[index].splice(0..0, labels);
+        self.instructions.remove(index);
+        self.lines.remove(index);
+    }
+}
+
+// Runs the optimizer over assembly source
+pub fn optimize(asm: &str) -> Result<String> {
+    let mut p = Peephole::new(asm)?;
+    p.optimize();
+    Ok(p.render())
+}
+
+// Runs the optimizer over assembly source with the source line of each instruction, see codegen::generate_with_lines
+pub fn optimize_with_lines(asm: &str, lines: &[Option<usize>]) -> Result<(String, Vec<Option<usize>>)> {
+    let mut p = Peephole::with_lines(asm, lines)?;
+    p.optimize();
+    Ok((p.render(), p.lines))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_then_load() {
+        assert_eq!(optimize("lda _1\nsta x\nlda x\nout\nhlt\nx dat 0\n_1 dat 1").unwrap(),
+            "lda _1\nsta x\nout\nhlt\nx dat 0\n_1 dat 1\n");
+    }
+
+    #[test]
+    fn test_labelled_load_kept() {
+        let asm = "sta x\nloop lda x\nbra loop\nx dat 0\n";
+        assert_eq!(optimize(asm).unwrap(), asm);
+    }
+
+    #[test]
+    fn test_redundant_loads_and_stores() {
+        assert_eq!(optimize("lda a\nlda b\nsta b\nsta c\nsta c\nhlt\na dat 1\nb dat 2\nc dat 0").unwrap(),
+            "lda b\nsta c\nhlt\na dat 1\nb dat 2\nc dat 0\n");
+    }
+
+    #[test]
+    fn test_branch_to_next() {
+        assert_eq!(optimize("lda x\nbrz end\nbra end\nend\nhlt\nx dat 0").unwrap(), "lda x\nend hlt\nx dat 0\n");
+    }
+
+    #[test]
+    fn test_unreachable() {
+        assert_eq!(optimize("f lda x\nret\nout\nout\ng ret\ndat 1\nx dat 0").unwrap(), "f lda x\nret\ng ret\ndat 1\nx dat 0\n");
+    }
+
+    #[test]
+    fn test_labels_move_on_removal() {
+        assert_eq!(optimize("start bra a\na lda x\nEND").unwrap(), "start\na lda x\nEND\n");
+    }
+
+    #[test]
+    fn test_lines_follow_instructions() {
+        let (asm, lines) = optimize_with_lines("lda _1\nsta x\nlda x\nout\nhlt\nx dat 0\n_1 dat 1", &[Some(1), Some(1), Some(2), Some(2)]).unwrap();
+        assert_eq!(asm, "lda _1\nsta x\nout\nhlt\nx dat 0\n_1 dat 1\n");
+        assert_eq!(lines, vec![Some(1), Some(1), Some(2), None, None, None]);
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::assembler::disassembler::{disassemble, label};
+use crate::machine::machine::Machine;
+use crate::machine::memory::Cell;
+use crate::machine::snapshot::Snapshot;
+
+const HELP: &str = "\
+step [n]             execute n instructions (default 1), also s
+continue             run to a breakpoint, halt or fault, also c
+back [n]             step back over n instructions (default 1), also k
+rcontinue            run back to a breakpoint or the start of the recording, also rc
+lastwrite <address>  run back to the last write of a cell, also lw
+break <address>      stop before executing the instruction at address, also b
+delete <address>     remove a breakpoint
+regs                 show the registers and call stack, also r
+mem <address> [n]    show n cells from address (default 8), also m
+save <path>          save a snapshot of the machine
+load <path>          restore a snapshot of the machine
+quit                 stop debugging, also q
+addresses are numbers (0x.. for hex) or labels";
+
+const NOT_RECORDING: &str = "not recording, the machine keeps no history to step back over";
+
+pub enum Reply {
+    OUTPUT(String),
+    QUIT,
+}
+
+// Line-oriented debugger, commands control and inspect a machine loaded with a program and its symbols
+pub struct Debugger {
+    pub machine: Machine,
+    symbols: HashMap<String, u16>,
+    breakpoints: BTreeSet<u16>,
+}
+
+impl Debugger {
+    pub fn new(machine: Machine, symbols: HashMap<String, u16>) -> Self {
+        Debugger { machine: machine, symbols: symbols, breakpoints: BTreeSet::new() }
+    }
+
+    pub fn command(&mut self, line: &str) -> Reply {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => { Ok(String::new()) }
+            ["step" | "s"] => { self.step(1) }
+            ["step" | "s", n] => { self.count(n).and_then(|n| self.step(n)) }
+            ["continue" | "c"] => { self.resume() }
+            ["back" | "k"] => { self.back(1) }
+            ["back" | "k", n] => { self.count(n).and_then(|n| self.back(n)) }
+            ["rcontinue" | "rc"] => { self.reverse() }
+            ["lastwrite" | "lw", address] => { self.address(address).and_then(|address| self.last_write(address)) }
+            ["break" | "b", address] => {
+                self.address(address).map(|address| {
+                    self.breakpoints.insert(address);
+                    format!("breakpoint at {}", self.location(address))
+                })
+            }
+            ["delete", address] => {
+                self.address(address).and_then(|address| match self.breakpoints.remove(&address) {
+                    true => { Ok(format!("deleted breakpoint at {}", self.location(address))) }
+                    false => { Err(format!("no breakpoint at {}", self.location(address))) }
+                })
+            }
+            ["regs" | "r"] => { Ok(self.registers()) }
+            ["mem" | "m", address] => { self.address(address).map(|address| self.cells(address, 8)) }
+            ["mem" | "m", address, n] => {
+                self.address(address).and_then(|address| Ok(self.cells(address, self.count(n)?)))
+            }
+            ["save", path] => {
+                std::fs::write(path, self.machine.snapshot().encode())
+                    .map(|_| format!("saved snapshot to {}", path))
+                    .map_err(|e| format!("could not write {}: {}", path, e))
+            }
+            ["load", path] => { self.load(path) }
+            ["quit" | "q"] => { return Reply::QUIT; }
+            ["help" | "h"] => { Ok(String::from(HELP)) }
+            _ => { Err(format!("unknown command, got: {}, try help", line.trim())) }
+        };
+
+        match result {
+            Ok(output) => { Reply::OUTPUT(output) }
+            Err(e) => { Reply::OUTPUT(format!("error: {}", e)) }
+        }
+    }
+
+    // The instruction about to execute
+    pub fn current(&self) -> String {
+        if self.machine.halted() {
+            return String::from("halted");
+        }
+
+        let pc = self.machine.pc();
+        match self.machine.memory.fetch(pc) {
+            Ok(cell) => { format!("{}  {}", self.location(pc), self.disassemble(cell)) }
+            Err(fault) => { format!("{}  {}", self.location(pc), fault) }
+        }
+    }
+
+    fn step(&mut self, n: usize) -> Result<String, String> {
+        for _ in 0..n {
+            if self.machine.halted() {
+                break;
+            }
+
+            self.machine.step().map_err(|fault| format!("machine fault: {}", fault))?;
+        }
+
+        Ok(self.current())
+    }
+
+    fn resume(&mut self) -> Result<String, String> {
+        self.machine.step().map_err(|fault| format!("machine fault: {}", fault))?;
+        while !self.machine.halted() && !self.breakpoints.contains(&self.machine.pc()) {
+            self.machine.step().map_err(|fault| format!("machine fault: {}", fault))?;
+        }
+
+        Ok(self.current())
+    }
+
+    fn back(&mut self, n: usize) -> Result<String, String> {
+        self.step_back()?;
+        for _ in 1..n {
+            if self.machine.step_back().is_none() {
+                break;
+            }
+        }
+
+        Ok(self.current())
+    }
+
+    fn reverse(&mut self) -> Result<String, String> {
+        self.step_back()?;
+        while !self.breakpoints.contains(&self.machine.pc()) && self.machine.step_back().is_some() {}
+        Ok(self.current())
+    }
+
+    // Stops before the STA which last wrote to the address, showing the value it overwrote
+    fn last_write(&mut self, address: u16) -> Result<String, String> {
+        let record = self.machine.record().ok_or(NOT_RECORDING)?;
+        let n = record.iter().rev().position(|delta| delta.write.is_some_and(|(written, _)| written == address))
+            .ok_or(format!("no recorded write to {}", self.location(address)))?;
+
+        let mut delta = None;
+        for _ in 0..=n {
+            delta = self.machine.step_back();
+        }
+
+        let (_, previous) = delta.and_then(|delta| delta.write).unwrap();
+        Ok(format!("{}\nwas {}", self.current(), previous))
+    }
+
+    fn step_back(&mut self) -> Result<(), String> {
+        let recording = self.machine.record().is_some();
+        match (recording, self.machine.step_back()) {
+            (false, _) => { Err(String::from(NOT_RECORDING)) }
+            (true, None) => { Err(String::from("at the start of the recording")) }
+            (true, Some(_)) => { Ok(()) }
+        }
+    }
+
+    fn load(&mut self, path: &str) -> Result<String, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+        let snapshot = Snapshot::decode(&bytes).map_err(|e| e.to_string())?;
+        self.machine.restore(&snapshot).map_err(|fault| format!("machine fault: {}", fault))?;
+        Ok(self.current())
+    }
+
+    fn registers(&self) -> String {
+        let (negative, carry) = self.machine.flags();
+        let mut out = format!(
+            "pc {}  acc {}  n {}  c {}  cycles {}{}\ncall stack:",
+            self.location(self.machine.pc()), self.machine.acc(), negative as u8, carry as u8, self.machine.cycles(),
+            if self.machine.halted() { "  halted" } else { "" },
+        );
+
+        if self.machine.call_stack().is_empty() {
+            out += " <empty>";
+        }
+
+        // innermost call first, each frame is the address of its CALL instruction
+        for return_address in self.machine.call_stack().iter().rev() {
+            out += &format!("\n  call at {}", self.location(return_address.wrapping_sub(1)));
+        }
+
+        out
+    }
+
+    fn cells(&self, address: u16, n: usize) -> String {
+        let mut lines: Vec<String> = vec![];
+        for offset in 0..n {
+            let address = address.wrapping_add(offset as u16);
+            match self.machine.memory.fetch(address) {
+                Ok(cell) => { lines.push(format!("{}  {}", self.location(address), self.disassemble(cell))); }
+                Err(fault) => {
+                    lines.push(format!("{}  {}", self.location(address), fault));
+                    break;
+                }
+            }
+        }
+
+        lines.join("\n")
+    }
+
+    fn disassemble(&self, cell: Cell) -> String {
+        disassemble(cell, &self.symbols)
+    }
+
+    // An address with its label, e.g. 0004 loop
+    fn location(&self, address: u16) -> String {
+        match self.label(address) {
+            Some(label) => { format!("{:04x} {}", address, label) }
+            None => { format!("{:04x}", address) }
+        }
+    }
+
+    fn label(&self, address: u16) -> Option<String> {
+        label(address, &self.symbols)
+    }
+
+    fn address(&self, word: &str) -> Result<u16, String> {
+        if let Some(address) = self.symbols.get(word) {
+            return Ok(*address);
+        }
+
+        let parsed = match word.strip_prefix("0x") {
+            Some(hex) => { u16::from_str_radix(hex, 16) }
+            None => { word.parse::<u16>() }
+        };
+
+        parsed.map_err(|_| format!("not an address or label, got: {}", word))
+    }
+
+    fn count(&self, word: &str) -> Result<usize, String> {
+        word.parse::<usize>().map_err(|_| format!("not a count, got: {}", word))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debugger() -> Debugger {
+        let program = crate::assemble("start lda ONE\nloop add ONE\nsta X\nbrz loop\nhlt\nONE dat 1\nX dat").unwrap();
+        let machine = Machine::builder().record(true).load(program.binary).unwrap();
+        Debugger::new(machine, program.symbol_table)
+    }
+
+    fn output(debugger: &mut Debugger, line: &str) -> String {
+        match debugger.command(line) {
+            Reply::OUTPUT(output) => { output }
+            Reply::QUIT => { String::from("<quit>") }
+        }
+    }
+
+    #[test]
+    fn test_step() {
+        let mut d = debugger();
+        assert_eq!(d.current(), "0000 start  lda ONE");
+        assert_eq!(output(&mut d, "step"), "0001 loop  add ONE");
+        assert_eq!(output(&mut d, "s 2"), "0003  brz loop");
+        assert_eq!(d.machine.acc(), 2);
+        assert_eq!(output(&mut d, "s 5"), "halted");
+    }
+
+    #[test]
+    fn test_break() {
+        let mut d = debugger();
+        assert_eq!(output(&mut d, "b 0x3"), "breakpoint at 0003");
+        assert_eq!(output(&mut d, "c"), "0003  brz loop");
+        assert_eq!(output(&mut d, "delete 3"), "deleted breakpoint at 0003");
+        assert_eq!(output(&mut d, "c"), "halted");
+        assert_eq!(output(&mut d, "b nowhere"), "error: not an address or label, got: nowhere");
+    }
+
+    #[test]
+    fn test_inspect() {
+        let mut d = debugger();
+        output(&mut d, "s 3");
+        assert_eq!(output(&mut d, "m X 1"), "0006 X  dat 2");
+        assert_eq!(output(&mut d, "m 4 2"), "0004  hlt\n0005 ONE  dat 1");
+        assert_eq!(output(&mut d, "r"), "pc 0003  acc 2  n 0  c 0  cycles 3\ncall stack: <empty>");
+        assert_eq!(output(&mut d, "frobnicate"), "error: unknown command, got: frobnicate, try help");
+        assert_eq!(output(&mut d, "q"), "<quit>");
+    }
+
+    #[test]
+    fn test_back() {
+        let mut d = debugger();
+        output(&mut d, "c");
+        assert_eq!(output(&mut d, "back"), "0004  hlt");
+        assert_eq!(output(&mut d, "k 2"), "0002  sta X");
+        assert_eq!((d.machine.acc(), d.machine.cycles()), (2, 2));
+        assert_eq!(output(&mut d, "b loop"), "breakpoint at 0001 loop");
+        assert_eq!(output(&mut d, "rc"), "0001 loop  add ONE");
+        assert_eq!(output(&mut d, "rc"), "0000 start  lda ONE");
+        assert_eq!(output(&mut d, "k"), "error: at the start of the recording");
+    }
+
+    #[test]
+    fn test_last_write() {
+        let mut d = debugger();
+        assert_eq!(output(&mut d, "lw X"), "error: no recorded write to 0006 X");
+        output(&mut d, "c");
+        assert_eq!(output(&mut d, "lw X"), "0002  sta X\nwas 0");
+        assert_eq!(d.machine.acc(), 2);
+
+        let program = crate::assemble("sta X\nhlt\nX dat").unwrap();
+        let mut d = Debugger::new(Machine::builder().load(program.binary).unwrap(), program.symbol_table);
+        output(&mut d, "s");
+        assert_eq!(output(&mut d, "k"), format!("error: {}", NOT_RECORDING));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("lmc-debugger-{}.lmcs", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut d = debugger();
+        output(&mut d, "s 2");
+        assert_eq!(output(&mut d, &format!("save {}", path)), format!("saved snapshot to {}", path));
+        output(&mut d, "c");
+        assert_eq!(output(&mut d, &format!("load {}", path)), "0002  sta X");
+        assert_eq!((d.machine.acc(), d.machine.cycles()), (2, 2));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,52 @@
+use std::fmt;
+use crate::machine::fault::Fault;
+
+// Errors of every stage of the toolchain, each carrying a description of the problem
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error {
+    Lexer(String),
+    Parser(String),
+    Macro(String),
+    Assembler(String),
+    Compiler(String),
+    Object(String),
+    Snapshot(String),
+    Fault(Fault),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+pub const ERROR_STATUS: i32 = 250; // process exit status of an error of the toolchain, above the band of faults
+
+impl Error {
+    // Process exit status reported for the error, a machine fault reports its own and every other error ERROR_STATUS
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Fault(fault) => { fault.exit_code() }
+            _ => { ERROR_STATUS }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Lexer(message) => { write!(f, "LexerError: {}", message) }
+            Error::Parser(message) => { write!(f, "SyntaxError: {}", message) }
+            Error::Macro(message) => { write!(f, "MacroError: {}", message) }
+            Error::Assembler(message) => { write!(f, "AssemblerError: {}", message) }
+            Error::Compiler(message) => { write!(f, "CompilerError: {}", message) }
+            Error::Object(message) => { write!(f, "ObjectError: {}", message) }
+            Error::Snapshot(message) => { write!(f, "SnapshotError: {}", message) }
+            Error::Fault(fault) => { write!(f, "machine fault: {}", fault) }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Fault> for Error {
+    fn from(fault: Fault) -> Self {
+        Error::Fault(fault)
+    }
+}
@@ -0,0 +1,151 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::machine::io::{Input, Output};
+use crate::machine::machine::Machine;
+
+// A golden-file case, a .lmc or .lmasc source with the output expected from running it in a .out file next to it.
+// Lines of a .in file next to it are fed to INP, sources sharing a stem share the input and expected output.
+pub struct Case {
+    pub source: PathBuf,
+    pub input: PathBuf,
+    pub expected: PathBuf,
+}
+
+pub enum Outcome {
+    PASSED,
+    FAILED(String), // line diff of the expected against the actual output
+    MISSING,        // no expected output yet, bless the case to create it
+    ERROR(String),  // the case could not be read, compiled or assembled
+}
+
+impl Case {
+    pub fn new(source: &Path) -> Self {
+        Case { source: source.to_path_buf(), input: source.with_extension("in"), expected: source.with_extension("out") }
+    }
+
+    // Builds and runs the source, returning its output
+    pub fn run(&self) -> std::result::Result<String, String> {
+        let source = std::fs::read_to_string(&self.source).map_err(|e| format!("could not read {}: {}", self.source.display(), e))?;
+        let input = match std::fs::read_to_string(&self.input) {
+            Ok(input) => { input }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => { String::new() }
+            Err(e) => { return Err(format!("could not read {}: {}", self.input.display(), e)); }
+        };
+
+        let is_lmc = self.source.extension().is_some_and(|extension| extension == "lmc");
+        run(&source, is_lmc, &input).map_err(|e| e.to_string())
+    }
+
+    pub fn check(&self) -> Outcome {
+        let actual = match self.run() {
+            Ok(actual) => { actual }
+            Err(e) => { return Outcome::ERROR(e); }
+        };
+
+        match std::fs::read_to_string(&self.expected) {
+            Ok(expected) if expected == actual => { Outcome::PASSED }
+            Ok(expected) => { Outcome::FAILED(diff(&expected, &actual)) }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => { Outcome::MISSING }
+            Err(e) => { Outcome::ERROR(format!("could not read {}: {}", self.expected.display(), e)) }
+        }
+    }
+
+    // Regenerates the expected output from the actual one
+    pub fn bless(&self) -> std::result::Result<(), String> {
+        let actual = self.run()?;
+        std::fs::write(&self.expected, actual).map_err(|e| format!("could not write {}: {}", self.expected.display(), e))
+    }
+}
+
+// Finds the cases among the given paths, directories are searched recursively for .lmc and .lmasc files
+pub fn discover(paths: &[PathBuf]) -> std::io::Result<Vec<Case>> {
+    let mut sources: Vec<PathBuf> = vec![];
+    for path in paths {
+        if path.is_dir() {
+            find_sources(path, &mut sources)?;
+        } else {
+            sources.push(path.clone());
+        }
+    }
+
+    Ok(sources.iter().map(|source| Case::new(source)).collect())
+}
+
+fn find_sources(directory: &Path, sources: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(directory)?.map(|entry| entry.map(|e| e.path())).collect::<std::io::Result<_>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            find_sources(&path, sources)?;
+        } else if path.extension().is_some_and(|extension| extension == "lmc" || extension == "lmasc") {
+            sources.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+// Builds .lmc or .lmasc source and runs it with the lines of input, a fault ends the output with a line naming it
+pub fn run(source: &str, is_lmc: bool, input: &str) -> Result<String> {
+    let asm = if is_lmc { crate::compile(source)? } else { source.to_string() };
+    let program = crate::assemble(&asm)?;
+
+    let mut m = Machine::builder().input(Input::lines(input)).output(Output::BUFFER(String::new())).build();
+    let result = m.load(program.binary).and_then(|_| m.emulate());
+
+    let mut out = m.output().to_string();
+    if let Err(fault) = result {
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+
+        out += &format!("machine fault: {}\n", fault);
+    }
+
+    Ok(out)
+}
+
+// Lists the lines which differ, by line number
+pub fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.split('\n').collect();
+    let actual: Vec<&str> = actual.split('\n').collect();
+
+    let mut out = String::new();
+    for line in 0..expected.len().max(actual.len()) {
+        let (e, a) = (expected.get(line), actual.get(line));
+        if e == a {
+            continue;
+        }
+
+        if let Some(e) = e {
+            out += &format!("{:>4} - {}\n", line + 1, e);
+        }
+
+        if let Some(a) = a {
+            out += &format!("{:>4} + {}\n", line + 1, a);
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        assert_eq!(run("inp\nout\nlda TEN\notc\nhlt\nTEN dat 10", false, "42\n").unwrap(), "42\n");
+        assert_eq!(run("inp\ninp", false, "1").unwrap(), "machine fault: end of input\n");
+        assert_eq!(run("use std;\nfn main() { println(input() + 1); return 0; }", true, "1").unwrap(), "2\n");
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(diff("a\nb\n", "a\nb\n"), "");
+        assert_eq!(diff("a\nb\n", "a\nc\n"), "   2 - b\n   2 + c\n");
+        assert_eq!(diff("a\n", "a\nb\n"), "   2 - \n   2 + b\n   3 + \n");
+    }
+}
@@ -0,0 +1,124 @@
+use std::path::Path;
+
+use crate::compiler::node::Node;
+use crate::error::Result;
+use crate::machine::io::{Input, Output};
+use crate::machine::machine::Machine;
+use crate::Options;
+
+pub const CYCLE_LIMIT: u64 = 1_000_000; // a test running longer is assumed not to halt
+
+pub enum Outcome {
+    PASSED,
+    FAILED(String), // the failed assertion or machine fault, followed by anything the test printed
+}
+
+// Names of the #[test] functions in .lmc source, in order
+pub fn tests(source: &str) -> Result<Vec<String>> {
+    let statements = match crate::parse(source)? {
+        Node::BLOCK(statements) => { *statements }
+        node => { vec![node] }
+    };
+
+    let mut tests: Vec<String> = vec![];
+    for statement in statements {
+        match statement {
+            Node::ATTRIBUTE(name, function) if name == "test" => {
+                if let Node::FUNCTION(identifier, _, _) = *function {
+                    tests.push(identifier);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(tests)
+}
+
+// Names of the #[test] functions in a file, files other than .lmc have none
+pub fn tests_in(path: &Path) -> std::result::Result<Vec<String>, String> {
+    if path.extension().is_none_or(|extension| extension != "lmc") {
+        return Ok(vec![]);
+    }
+
+    let source = std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    tests(&source).map_err(|e| e.to_string())
+}
+
+// Compiles the source with the test as its entry point and runs it in a fresh machine. A test passes when it halts
+// without a failed assertion, faults and running past the cycle limit fail it.
+pub fn run(source: &str, test: &str, options: &Options) -> Result<Outcome> {
+    let options = Options { opt_level: options.opt_level, entry: Some(test.to_string()) };
+    let module = crate::lower(source, &options)?;
+    let mut asm = crate::compiler::codegen::generate(&module);
+    if options.opt_level > 0 {
+        asm = crate::compiler::peephole::optimize(&asm)?;
+    }
+
+    let program = crate::assemble(&asm)?;
+    let mut m = Machine::builder()
+        .protect_code(true)
+        .detect_data_execution(true)
+        .cycle_limit(Some(CYCLE_LIMIT))
+        .input(Input::lines(""))
+        .output(Output::BUFFER(String::new()))
+        .build();
+
+    let result = m.load(program.binary).and_then(|_| m.emulate());
+
+    // the hlt of a failed assertion is the instruction before the pc
+    let halted_at = m.pc().wrapping_sub(1);
+    let mut message = match result {
+        Err(fault) => { format!("machine fault: {}", fault) }
+        Ok(()) => {
+            let failed = module.asserts.iter().find(|(label, _)| program.symbol_table.get(label) == Some(&halted_at));
+            match failed {
+                Some((_, assertion)) => { format!("assertion failed: {}", assertion) }
+                None => { return Ok(Outcome::PASSED); }
+            }
+        }
+    };
+
+    if !m.output().is_empty() {
+        message += &format!("\noutput:\n{}", m.output());
+    }
+
+    Ok(Outcome::FAILED(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "
+        fn double(x) { return x + x; }
+
+        #[test]
+        fn test_pass() { assert_eq(double(2), 4); }
+
+        #[test]
+        fn test_fail() { assert(double(2) == 5); }
+
+        #[test]
+        fn test_loop() { while 1 > 0 {} }
+    ";
+
+    fn outcome(test: &str) -> Option<String> {
+        match run(SOURCE, test, &Options::new()).unwrap() {
+            Outcome::PASSED => { None }
+            Outcome::FAILED(message) => { Some(message) }
+        }
+    }
+
+    #[test]
+    fn test_tests() {
+        assert_eq!(tests(SOURCE).unwrap(), vec!["test_pass", "test_fail", "test_loop"]);
+    }
+
+    #[test]
+    fn test_run() {
+        assert_eq!(outcome("test_pass"), None);
+        assert_eq!(outcome("test_fail"), Some(String::from("assertion failed: assert(double(2) == 5)")));
+        assert_eq!(outcome("test_loop"), Some(format!("machine fault: cycle limit reached after {} cycles", CYCLE_LIMIT)));
+    }
+}
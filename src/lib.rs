@@ -0,0 +1,215 @@
+#![allow(clippy::upper_case_acronyms, clippy::module_inception, clippy::box_collection, clippy::needless_return, clippy::redundant_field_names, clippy::new_without_default)]
+
+pub mod assembler;
+pub mod compiler;
+pub mod debugger;
+pub mod error;
+pub mod harness;
+pub mod machine;
+pub mod tui;
+
+mod reader;
+
+pub use assembler::object::Object;
+pub use assembler::program::Program;
+pub use compiler::compiler::Options;
+pub use error::{Error, Result};
+pub use machine::machine::{Machine, MachineBuilder};
+
+// Assembles .lmasc source into a binary image
+pub fn assemble(source: &str) -> Result<Program> {
+    let mut l = assembler::lexer::Lexer::new(source.chars().collect());
+    let mut e = assembler::macros::Expander::new(l.lex()?);
+    let tokens: Vec<assembler::lexer::Token> = e.expand()?;
+
+    let mut p = assembler::parser::Parser::with_lines(tokens, e.lines);
+    let (instructions, symbol_table) = p.parse()?;
+
+    let mut c = assembler::assembler::Compiler::new(instructions.clone(), symbol_table.clone());
+    let binary = c.compile()?;
+
+    Ok(Program { binary: binary, instructions: instructions, symbol_table: symbol_table, lines: p.lines })
+}
+
+// Lexes .lmc source into its token stream
+pub fn tokens(source: &str) -> Result<Vec<compiler::lexer::Token>> {
+    let mut l = compiler::lexer::Lexer::new(source.chars().collect());
+    l.lex()
+}
+
+// Parses .lmc source into its syntax tree
+pub fn parse(source: &str) -> Result<compiler::node::Node> {
+    let mut p = compiler::parser::Parser::new(tokens(source)?);
+    p.parse()
+}
+
+// Parses .lmc source with the line of each statement, for the compiler's debug info
+fn parse_with_lines(source: &str) -> Result<compiler::node::Node> {
+    let mut l = compiler::lexer::Lexer::new(source.chars().collect());
+    let tokens = l.lex()?;
+    let mut p = compiler::parser::Parser::with_lines(tokens, l.lines);
+    p.parse()
+}
+
+// Lowers .lmc source into the intermediate representation codegen turns into assembly
+pub fn lower(source: &str, options: &Options) -> Result<compiler::ir::Module> {
+    lower_tree(parse(source)?, options)
+}
+
+fn lower_tree(mut ast: compiler::node::Node, options: &Options) -> Result<compiler::ir::Module> {
+    // literal expressions out of the word's range are errors at every level, only -O1 keeps the folded tree
+    if options.opt_level > 0 {
+        ast = compiler::folder::Folder::new().fold(ast)?;
+    } else {
+        compiler::folder::Folder::literals().fold(ast.clone())?;
+    }
+
+    let mut c = match &options.entry {
+        Some(entry) => { compiler::compiler::Compiler::with_entry(entry) }
+        None => { compiler::compiler::Compiler::new() }
+    };
+
+    c.lower(ast)
+}
+
+// Compiles .lmc source into .lmasc assembly
+pub fn compile(source: &str) -> Result<String> {
+    compile_with(source, &Options::new())
+}
+
+// Compiles .lmc source with options, at opt_level 1 constants in the syntax tree are folded and the peephole
+// optimizer runs over the assembly
+pub fn compile_with(source: &str, options: &Options) -> Result<String> {
+    Ok(compile_with_lines(source, options)?.0)
+}
+
+// Compiles .lmc source with options along with its debug info, the .lmc line of each instruction of the assembly.
+// An instruction's index is its address once assembled, the libraries and data section have no lines.
+pub fn compile_with_lines(source: &str, options: &Options) -> Result<(String, Vec<Option<usize>>)> {
+    let module = lower_tree(parse_with_lines(source)?, options)?;
+    let (asm, lines) = compiler::codegen::generate_with_lines(&module);
+    if options.opt_level == 0 {
+        return Ok((asm, lines));
+    }
+
+    compiler::peephole::optimize_with_lines(&asm, &lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble() {
+        let program = assemble("lda ONE\nhlt\nONE dat 1").unwrap();
+        assert_eq!(program.binary, vec![3, 0, 2, 0, 0, 0, 12, 0, 1]);
+        assert_eq!(program.symbol_table.get("ONE"), Some(&2));
+        assert_eq!(program.lines, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_assemble_error() {
+        assert_eq!(assemble("lda TWO").unwrap_err(), Error::Assembler(String::from("undefined label found in compiler, got: TWO")));
+    }
+
+    #[test]
+    fn test_compile_and_assemble() {
+        let asm = compile("use std;\nfn _main() {\n    print(1 + 2);\n}").unwrap();
+        assert!(assemble(&asm).is_ok());
+    }
+
+    #[test]
+    fn test_object() {
+        let program = assemble("lda ONE\nhlt\nONE dat 1").unwrap();
+        let object = Object::decode(&program.object().encode()).unwrap();
+        assert_eq!(object.binary, program.binary);
+        assert_eq!(object.symbol_table, program.symbol_table);
+        assert_eq!(object.lines, program.lines);
+    }
+
+    #[test]
+    fn test_compile_deterministic() {
+        let source = "use std;\nfn _main() {\n    print(3);\n    print(1);\n    print(2);\n}";
+        let asm = compile(source).unwrap();
+        for _ in 0..8 {
+            assert_eq!(compile(source).unwrap(), asm);
+        }
+    }
+
+    #[test]
+    fn test_compile_with() {
+        let source = "use std;\nfn _main() {\n    let x = 3 + (2 - 1);\n    print(x);\n}";
+        assert!(compile(source).is_err());
+
+        let asm = compile_with(source, &Options { opt_level: 1, entry: None }).unwrap();
+        assert!(asm.contains("_4 dat 4"));
+        assert!(assemble(&asm).is_ok());
+    }
+
+    #[test]
+    fn test_compile_with_lines() {
+        let source = "use std;\nfn _main() {\n    let x = 1;\n    if x == 1 {\n        print(x);\n    }\n}";
+        let (asm, lines) = compile_with_lines(source, &Options::new()).unwrap();
+        assert_eq!(asm, compile(source).unwrap());
+
+        let instructions: Vec<&str> = asm.lines().filter(|line| *line != "_main" && !line.starts_with("_l")).collect();
+        let at = |line: usize| -> Vec<&str> {
+            lines.iter().enumerate().filter(|(_, l)| **l == Some(line)).map(|(address, _)| instructions[address]).collect()
+        };
+
+        assert_eq!(&lines[..3], &[None, None, None]);
+        assert_eq!(at(3), vec!["lda _1", "sta x"]);
+        assert_eq!(at(4), vec!["lda x", "sub _1", "brz _l1", "bra _l0", "bra _l0"]);
+        assert_eq!(at(5), vec!["lda x", "sta _p0", "call print", "lda _ret"]);
+        assert_eq!(at(2), vec!["lda _0", "sta _ret", "ret"]);
+
+        let (optimized, lines) = compile_with_lines(source, &Options { opt_level: 1, entry: None }).unwrap();
+        assert_eq!(optimized, compile_with(source, &Options { opt_level: 1, entry: None }).unwrap());
+        assert_eq!(lines.len(), assemble(&optimized).unwrap().binary.len() / 3);
+        assert_eq!(lines.iter().filter(|line| **line == Some(5)).count(), 3);
+    }
+
+    #[test]
+    fn test_compile_with_lines_nested() {
+        // the jump ending the while's body is on the line of the while, not of the if nested in it
+        let source = "fn _main() {\n    let x = 3;\n    while x > 0 {\n        if x == 2 {\n            x = 0;\n        }\n        x = x - 1;\n    }\n}";
+        let (asm, lines) = compile_with_lines(source, &Options::new()).unwrap();
+        let instructions: Vec<&str> = asm.lines().filter(|line| *line != "_main" && !line.starts_with("_l")).collect();
+        let last = |line: usize| instructions[lines.iter().rposition(|l| *l == Some(line)).unwrap()];
+
+        assert_eq!(last(3), "bra _l0");
+        assert_eq!(last(4), "bra _l3");
+        assert_eq!(last(5), "sta x");
+        assert_eq!(last(1), "ret");
+    }
+
+    #[test]
+    fn test_main_without_return_halts_with_zero() {
+        // input leaves its value in _ret, the entry point must not pass it on as main's return value
+        let asm = compile("use std;\nfn main() {\n    let x = input();\n    print(x);\n}").unwrap();
+        let input = machine::io::Input::values("42");
+        let mut m = Machine::builder().input(input).output(machine::io::Output::BUFFER(String::new())).load(assemble(&asm).unwrap().binary).unwrap();
+        m.emulate().unwrap();
+        assert_eq!(m.acc(), 0);
+    }
+
+    #[test]
+    fn test_compile_error() {
+        assert!(matches!(compile("let x = ;"), Err(Error::Parser(_))));
+    }
+
+    #[test]
+    fn test_constant_range_at_every_level() {
+        let source = "fn _main() {\n    let x = 1 - 2;\n}";
+        let error = Err(Error::Compiler(String::from("constant expression overflows a 16-bit word, got: 1 - 2 = -1")));
+        assert_eq!(compile_with(source, &Options { opt_level: 0, entry: None }), error);
+        assert_eq!(compile_with(source, &Options { opt_level: 1, entry: None }), error);
+    }
+
+    #[test]
+    fn test_propagated_value_wraps_at_every_level() {
+        let source = "use std;\nfn main() {\n    let x = 0;\n    x = x - 1;\n    println(x);\n    return 0;\n}";
+        assert!(compile_with(source, &Options { opt_level: 0, entry: None }).is_ok());
+        assert!(compile_with(source, &Options { opt_level: 1, entry: None }).is_ok());
+    }
+}
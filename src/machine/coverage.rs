@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+
+use crate::machine::memory::Memory;
+use crate::machine::profile::Profile;
+
+const BRZ: u8 = 0b0110;
+const BGT: u8 = 0b0111;
+const BLT: u8 = 0b1011;
+const DAT: u8 = 0b1100;
+
+// A conditional branch of a line, either way it can go is a branch in lcov's terms
+pub struct Branch {
+    pub address: u16,
+    pub executed: u64,
+    pub taken: u64,
+}
+
+// Source lines executed during a profiled run, from the source line of each address. A line's hits are those of its
+// most executed instruction, lines without instructions are not counted and neither are DAT cells.
+pub struct Coverage {
+    lines: BTreeMap<usize, u64>,
+    branches: BTreeMap<usize, Vec<Branch>>,
+}
+
+impl Coverage {
+    pub fn new(profile: &Profile, memory: &Memory, lines: &[Option<usize>]) -> Self {
+        let mut coverage = Coverage { lines: BTreeMap::new(), branches: BTreeMap::new() };
+        for (address, line) in lines.iter().enumerate() {
+            let (address, Some(line)) = (address as u16, *line) else { continue };
+            let opcode = match memory.opcode(address) {
+                Ok(DAT) | Err(_) => { continue; }
+                Ok(opcode) => { opcode }
+            };
+
+            let hits = coverage.lines.entry(line).or_insert(0);
+            *hits = (*hits).max(profile.count_at(address));
+
+            if matches!(opcode, BRZ | BGT | BLT) {
+                let branch = Branch { address: address, executed: profile.count_at(address), taken: profile.jumps_at(address) };
+                coverage.branches.entry(line).or_default().push(branch);
+            }
+        }
+
+        coverage
+    }
+
+    // Times a line was executed, None when it has no instructions
+    pub fn hits(&self, line: usize) -> Option<u64> {
+        self.lines.get(&line).copied()
+    }
+
+    pub fn branches(&self, line: usize) -> &[Branch] {
+        self.branches.get(&line).map(|branches| branches.as_slice()).unwrap_or_default()
+    }
+
+    // Lines executed and lines with instructions
+    pub fn lines_hit(&self) -> (usize, usize) {
+        (self.lines.values().filter(|hits| **hits > 0).count(), self.lines.len())
+    }
+
+    // Ways the branches went and ways they could go, each branch is taken or falls through
+    pub fn branches_hit(&self) -> (usize, usize) {
+        let branches = self.branches.values().flatten();
+        let hit = branches.clone().map(|branch| (branch.taken > 0) as usize + (branch.executed > branch.taken) as usize).sum();
+        (hit, branches.count() * 2)
+    }
+
+    // The source annotated with the hits of each line, like gcov: - for lines without instructions and ##### for
+    // lines never executed, followed by where their branches went
+    pub fn text(&self, path: &str, source: &str) -> String {
+        let percent = |(hit, total): (usize, usize)| if total == 0 { 100.0 } else { hit as f64 * 100.0 / total as f64 };
+        let (lines, branches) = (self.lines_hit(), self.branches_hit());
+        let mut out = format!(
+            "coverage: {}, {} of {} lines ({:.1}%), {} of {} branches ({:.1}%)\n",
+            path, lines.0, lines.1, percent(lines), branches.0, branches.1, percent(branches),
+        );
+
+        for (index, text) in source.lines().enumerate() {
+            let count = match self.hits(index + 1) {
+                Some(0) => { String::from("#####") }
+                Some(hits) => { hits.to_string() }
+                None => { String::from("-") }
+            };
+
+            out += &format!("{:>9}:{:>5}:{}\n", count, index + 1, text);
+            for branch in self.branches(index + 1) {
+                out += &format!(
+                    "{:>16}branch at {:04x} taken {}, fell through {}\n", "", branch.address, branch.taken, branch.executed - branch.taken,
+                );
+            }
+        }
+
+        out
+    }
+
+    // The lcov tracefile of the source, for genhtml and coverage services. Branch blocks are their addresses, the
+    // first branch of a block is the taken one and the second falls through.
+    pub fn lcov(&self, path: &str) -> String {
+        let mut out = format!("TN:\nSF:{}\n", path);
+        for (line, hits) in &self.lines {
+            out += &format!("DA:{},{}\n", line, hits);
+        }
+
+        for (line, branch) in self.branches.iter().flat_map(|(line, branches)| branches.iter().map(move |branch| (line, branch))) {
+            let (taken, fell) = if branch.executed == 0 {
+                (String::from("-"), String::from("-"))
+            } else {
+                (branch.taken.to_string(), (branch.executed - branch.taken).to_string())
+            };
+
+            out += &format!("BRDA:{},{},0,{}\nBRDA:{},{},1,{}\n", line, branch.address, taken, line, branch.address, fell);
+        }
+
+        let (lines, branches) = (self.lines_hit(), self.branches_hit());
+        out += &format!("BRF:{}\nBRH:{}\nLF:{}\nLH:{}\nend_of_record\n", branches.1, branches.0, lines.1, lines.0);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::io::Input;
+    use crate::machine::machine::Machine;
+
+    const SOURCE: &str = "inp\nbrz ZERO\nout\nhlt\nZERO hlt\nONE dat 1";
+
+    fn covered(input: &str) -> Coverage {
+        let program = crate::assemble(SOURCE).unwrap();
+        let mut m = Machine::builder().input(Input::values(input)).profile(true).load(program.binary).unwrap();
+        m.emulate().unwrap();
+
+        let lines: Vec<Option<usize>> = program.lines.iter().map(|line| Some(*line)).collect();
+        Coverage::new(m.profile().unwrap(), &m.memory, &lines)
+    }
+
+    #[test]
+    fn test_lines() {
+        let coverage = covered("5");
+        assert_eq!((coverage.hits(1), coverage.hits(3), coverage.hits(5), coverage.hits(6)), (Some(1), Some(1), Some(0), None));
+        assert_eq!(coverage.lines_hit(), (4, 5));
+        assert_eq!(coverage.branches_hit(), (1, 2));
+    }
+
+    #[test]
+    fn test_text() {
+        let text = covered("0").text("zero.lmasc", SOURCE);
+        assert!(text.starts_with("coverage: zero.lmasc, 3 of 5 lines (60.0%), 1 of 2 branches (50.0%)\n"));
+        assert!(text.contains("        1:    2:brz ZERO\n                branch at 0001 taken 1, fell through 0\n"));
+        assert!(text.contains("    #####:    3:out\n"));
+        assert!(text.ends_with("        -:    6:ONE dat 1\n"));
+    }
+
+    #[test]
+    fn test_lcov() {
+        let lcov = covered("5").lcov("zero.lmasc");
+        assert!(lcov.starts_with("TN:\nSF:zero.lmasc\nDA:1,1\nDA:2,1\nDA:3,1\nDA:4,1\nDA:5,0\n"));
+        assert!(lcov.ends_with("BRDA:2,1,0,0\nBRDA:2,1,1,1\nBRF:2\nBRH:1\nLF:5\nLH:4\nend_of_record\n"));
+    }
+}
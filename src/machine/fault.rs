@@ -0,0 +1,88 @@
+use std::fmt;
+
+// Process exit statuses come in bands that never overlap: 0 to 239 a program halting with --exit-code, 240 to 249 a
+// machine fault and 250 an error of the toolchain, see Error::exit_code
+pub const FAULT_STATUS: i32 = 240; // status of the first fault, the band of faults starts here
+
+// Raised by the machine in place of a panic, the pc of the machine is left on the faulting instruction
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Fault {
+    MemoryOutOfBounds(usize), // address (or image size) beyond the end of memory
+    WriteProtected(u16),      // STA to an instruction cell of the loaded program
+    ExecuteData(u16),         // pc reached a DAT cell, or ran off the end of the loaded program
+    InvalidOpcode(u8),
+    InvalidCharacter(u16),    // OTC of a value which is not a char
+    StackOverflow(usize),     // CALL beyond the maximum call depth
+    StackUnderflow,           // RET with no active call
+    EndOfInput,               // INP with no input left
+    InvalidInput(String),     // INP of a line which is not a number, or INC of one which is not a single char
+    CycleLimit(u64),          // ran for the maximum number of cycles without halting
+}
+
+impl Fault {
+    // Process exit status reported for the fault, distinct for every kind of fault
+    pub fn exit_code(&self) -> i32 {
+        let offset = match self {
+            Fault::MemoryOutOfBounds(_) => { 0 }
+            Fault::WriteProtected(_) => { 1 }
+            Fault::ExecuteData(_) => { 2 }
+            Fault::InvalidOpcode(_) => { 3 }
+            Fault::InvalidCharacter(_) => { 4 }
+            Fault::StackOverflow(_) => { 5 }
+            Fault::StackUnderflow => { 6 }
+            Fault::EndOfInput => { 7 }
+            Fault::InvalidInput(_) => { 8 }
+            Fault::CycleLimit(_) => { 9 }
+        };
+
+        FAULT_STATUS + offset
+    }
+}
+
+// Process exit status of a program which halted with the accumulator, capped below the statuses of faults and errors
+pub fn halt_status(acc: u16) -> i32 {
+    (acc as i32).min(FAULT_STATUS - 1)
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::MemoryOutOfBounds(address) => { write!(f, "memory access out of bounds, address: {:04x}", address) }
+            Fault::WriteProtected(address) => { write!(f, "write to protected code cell, address: {:04x}", address) }
+            Fault::ExecuteData(address) => { write!(f, "execution of data, address: {:04x}", address) }
+            Fault::InvalidOpcode(opcode) => { write!(f, "invalid opcode: {:#06b}", opcode) }
+            Fault::InvalidCharacter(value) => { write!(f, "invalid char for otc: {}", value) }
+            Fault::StackOverflow(size) => { write!(f, "stack overflow, exceeded maximum call depth of {}", size) }
+            Fault::StackUnderflow => { write!(f, "stack underflow, ret with an empty call stack") }
+            Fault::EndOfInput => { write!(f, "end of input") }
+            Fault::CycleLimit(limit) => { write!(f, "cycle limit reached after {} cycles", limit) }
+            Fault::InvalidInput(line) => { write!(f, "invalid input, got: {:?}", line) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_distinct() {
+        let faults = [
+            Fault::MemoryOutOfBounds(0), Fault::WriteProtected(0), Fault::ExecuteData(0), Fault::InvalidOpcode(0),
+            Fault::InvalidCharacter(0), Fault::StackOverflow(0), Fault::StackUnderflow, Fault::EndOfInput,
+            Fault::InvalidInput(String::new()), Fault::CycleLimit(0),
+        ];
+
+        let mut codes: Vec<i32> = faults.iter().map(|fault| fault.exit_code()).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), faults.len());
+        assert!(codes.iter().all(|code| (FAULT_STATUS..crate::error::ERROR_STATUS).contains(code)));
+    }
+
+    #[test]
+    fn test_halt_status() {
+        assert_eq!((halt_status(0), halt_status(12), halt_status(239)), (0, 12, 239));
+        assert_eq!((halt_status(240), halt_status(250), halt_status(u16::MAX)), (239, 239, 239));
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Write as _;
+use std::io::{BufRead, Write};
+use crate::machine::fault::Fault;
+
+// Where INP reads its lines from
+pub enum Input {
+    CONSOLE(Option<Box<rustyline::DefaultEditor>>), // prompted interactively, invalid values are prompted for again
+    STDIN,                                          // read from stdin when it is not a terminal, e.g. piped
+    LINES(VecDeque<String>),                        // given up front, e.g. from a file
+}
+
+impl Input {
+    // The editor is created on the first read and kept for the ones after it
+    pub fn console() -> Self {
+        Input::CONSOLE(None)
+    }
+
+    pub fn lines(text: &str) -> Self {
+        Input::LINES(text.lines().map(String::from).collect())
+    }
+
+    // Values separated by commas, e.g. "5,+,3"
+    pub fn values(text: &str) -> Self {
+        Input::LINES(text.split(',').map(String::from).collect())
+    }
+
+    // Lines given up front and not read yet, None for the console and stdin
+    pub fn queued(&self) -> Option<&VecDeque<String>> {
+        match self {
+            Input::LINES(lines) => { Some(lines) }
+            _ => { None }
+        }
+    }
+
+    // Queues a line after those given up front, the console and stdin are read as they are
+    pub fn push(&mut self, line: &str) {
+        if let Input::LINES(lines) = self {
+            lines.push_back(line.to_string());
+        }
+    }
+
+    pub fn read_line(&mut self) -> Result<String, Fault> {
+        match self {
+            Input::CONSOLE(editor) => {
+                if editor.is_none() {
+                    *editor = Some(Box::new(rustyline::DefaultEditor::new().map_err(|_| Fault::EndOfInput)?));
+                }
+
+                editor.as_mut().unwrap().readline("").map_err(|_| Fault::EndOfInput)
+            }
+            Input::STDIN => {
+                let mut line = String::new();
+                match std::io::stdin().lock().read_line(&mut line) {
+                    Ok(0) | Err(_) => { Err(Fault::EndOfInput) }
+                    Ok(_) => { Ok(line.trim_end_matches(['\n', '\r']).to_string()) }
+                }
+            }
+            Input::LINES(lines) => { lines.pop_front().ok_or(Fault::EndOfInput) }
+        }
+    }
+
+    // Reads the value of the next line
+    pub fn read_value(&mut self, kind: Value) -> Result<u16, Fault> {
+        loop {
+            let line = self.read_line()?;
+            match (parse_input(&line, kind), &self) {
+                (Err(fault), Input::CONSOLE(_)) => { eprintln!("{}, {}, try again", fault, kind); }
+                (result, _) => { return result; }
+            }
+        }
+    }
+}
+
+// Kind of value read, a number by INP and a char by INC
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Value {
+    NUMBER,
+    CHAR,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::NUMBER => { write!(f, "expected a number from 0 to 65535") }
+            Value::CHAR => { write!(f, "expected a single char") }
+        }
+    }
+}
+
+// What INP does once the input is exhausted
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EndOfInput {
+    FAULT, // fault with Fault::EndOfInput
+    ZERO,  // read 0
+    HALT,  // halt the machine
+}
+
+// A number, or a single char which reads as its code. A char may be whitespace, surrounding whitespace is ignored
+// otherwise.
+pub fn parse_input(line: &str, kind: Value) -> Result<u16, Fault> {
+    let value = match kind {
+        Value::NUMBER => { line.trim().parse::<u16>().ok() }
+        Value::CHAR => { single_char(line).or(single_char(line.trim())).map(|ch| ch as u16) }
+    };
+
+    value.ok_or(Fault::InvalidInput(line.to_string()))
+}
+
+fn single_char(line: &str) -> Option<char> {
+    let mut chars = line.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => { Some(ch) }
+        _ => { None }
+    }
+}
+
+// Where OUT and OTC write to
+pub enum Output {
+    STDOUT,
+    BUFFER(String), // captured, e.g. to compare against expected output
+}
+
+impl Output {
+    // Formats the value straight into the sink, OUT and OTC run in the emulator's loop and allocate nothing
+    pub fn write(&mut self, value: impl fmt::Display) {
+        match self {
+            Output::STDOUT => { print!("{}", value); }
+            Output::BUFFER(buffer) => { write!(buffer, "{}", value).ok(); }
+        }
+    }
+
+    // Stdout is line buffered, output without a newline shows once flushed
+    pub fn flush(&mut self) {
+        if let Output::STDOUT = self {
+            std::io::stdout().flush().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_input() {
+        assert_eq!(parse_input("42\n", Value::NUMBER), Ok(42));
+        assert_eq!(parse_input("7", Value::NUMBER), Ok(7));
+        assert_eq!(parse_input("+", Value::NUMBER), Err(Fault::InvalidInput(String::from("+"))));
+        assert_eq!(parse_input("70000", Value::NUMBER), Err(Fault::InvalidInput(String::from("70000"))));
+
+        assert_eq!(parse_input("7", Value::CHAR), Ok('7' as u16));
+        assert_eq!(parse_input(" ", Value::CHAR), Ok(' ' as u16));
+        assert_eq!(parse_input(" + ", Value::CHAR), Ok('+' as u16));
+        assert_eq!(parse_input("abc", Value::CHAR), Err(Fault::InvalidInput(String::from("abc"))));
+        assert_eq!(parse_input("", Value::CHAR), Err(Fault::InvalidInput(String::new())));
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut input = Input::lines("1\n+\n");
+        assert_eq!(input.read_line(), Ok(String::from("1")));
+        assert_eq!(input.read_line(), Ok(String::from("+")));
+        assert_eq!(input.read_line(), Err(Fault::EndOfInput));
+
+        input.push("2");
+        assert_eq!(input.queued(), Some(&VecDeque::from([String::from("2")])));
+        assert_eq!(input.read_line(), Ok(String::from("2")));
+        assert_eq!(Input::STDIN.queued(), None);
+    }
+
+    #[test]
+    fn test_values() {
+        let mut input = Input::values("5,+, 3,x1");
+        assert_eq!(input.read_value(Value::NUMBER), Ok(5));
+        assert_eq!(input.read_value(Value::CHAR), Ok('+' as u16));
+        assert_eq!(input.read_value(Value::NUMBER), Ok(3));
+        assert_eq!(input.read_value(Value::NUMBER), Err(Fault::InvalidInput(String::from("x1"))));
+        assert_eq!(input.read_value(Value::CHAR), Err(Fault::EndOfInput));
+    }
+}
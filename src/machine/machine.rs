@@ -1,7 +1,17 @@
 use std::vec;
+use crate::machine::fault::Fault;
+use crate::machine::io::{EndOfInput, Input, Output, Value};
+use crate::machine::memory::{Cell, Memory, MEMORY_SIZE, WORD_SIZE};
+use crate::machine::profile::Profile;
+use crate::machine::record::{Delta, Record, StackOp, RECORD_LIMIT};
+use crate::machine::snapshot::Snapshot;
+
+const STA: u8 = 0b0100;
+const DAT: u8 = 0b1100;
+pub const STACK_SIZE: usize = 256; // default maximum call depth
 
 pub struct Machine {
-    pub memory: Vec<u8>,
+    pub memory: Memory,
     stack: Vec<u16>,
     pc: u16,
     acc: u16,
@@ -9,91 +19,433 @@ pub struct Machine {
 
     n: bool, // Negative flag
     c: bool, // Carry flag
+
+    stack_size: usize, // maximum call depth, CALL beyond it faults
+    code_size: usize, // cells occupied by the loaded program
+    protect_code: bool, // fault on STA to an instruction (non DAT) cell of the loaded program
+    detect_data_execution: bool, // fault when the pc reaches a DAT cell or leaves the loaded program
+    cycle_limit: Option<u64>, // fault after this many cycles without halting
+    cycles: u64,
+    record: Option<Record>, // deltas of the executed instructions, to step back over them
+    profile: Option<Profile>, // instructions executed per address and function
+
+    input: Input,
+    end_of_input: EndOfInput,
+    output: Output,
+}
+
+// Configures and loads a Machine, e.g. Machine::builder().stack_size(16).protect_code(true).load(image)
+pub struct MachineBuilder {
+    memory_size: usize,
+    stack_size: usize,
+    protect_code: bool,
+    detect_data_execution: bool,
+    cycle_limit: Option<u64>,
+    record: bool,
+    profile: bool,
+    input: Input,
+    end_of_input: EndOfInput,
+    output: Output,
+}
+
+impl MachineBuilder {
+    pub fn memory_size(mut self, cells: usize) -> Self {
+        self.memory_size = cells;
+        self
+    }
+
+    pub fn stack_size(mut self, depth: usize) -> Self {
+        self.stack_size = depth;
+        self
+    }
+
+    pub fn protect_code(mut self, protect_code: bool) -> Self {
+        self.protect_code = protect_code;
+        self
+    }
+
+    pub fn detect_data_execution(mut self, detect_data_execution: bool) -> Self {
+        self.detect_data_execution = detect_data_execution;
+        self
+    }
+
+    pub fn cycle_limit(mut self, cycle_limit: Option<u64>) -> Self {
+        self.cycle_limit = cycle_limit;
+        self
+    }
+
+    // Records the last RECORD_LIMIT instructions, which step_back undoes
+    pub fn record(mut self, record: bool) -> Self {
+        self.record = record;
+        self
+    }
+
+    // Counts the instructions executed per address and function, see Machine::profile
+    pub fn profile(mut self, profile: bool) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn input(mut self, input: Input) -> Self {
+        self.input = input;
+        self
+    }
+
+    pub fn end_of_input(mut self, end_of_input: EndOfInput) -> Self {
+        self.end_of_input = end_of_input;
+        self
+    }
+
+    pub fn output(mut self, output: Output) -> Self {
+        self.output = output;
+        self
+    }
+
+    pub fn build(self) -> Machine {
+        let mut m = Machine::new();
+        m.memory = Memory::with_size(self.memory_size);
+        m.stack_size = self.stack_size;
+        m.protect_code = self.protect_code;
+        m.detect_data_execution = self.detect_data_execution;
+        m.cycle_limit = self.cycle_limit;
+        m.record = if self.record { Some(Record::new(RECORD_LIMIT)) } else { None };
+        m.profile = if self.profile { Some(Profile::new(0)) } else { None };
+        m.input = self.input;
+        m.end_of_input = self.end_of_input;
+        m.output = self.output;
+        m
+    }
+
+    pub fn load(self, program: Vec<u8>) -> Result<Machine, Fault> {
+        let mut m = self.build();
+        m.load(program)?;
+        Ok(m)
+    }
+
+    // Continues from a snapshot, the memory size is that of the snapshot
+    pub fn resume(self, snapshot: &Snapshot) -> Result<Machine, Fault> {
+        let mut m = self.build();
+        m.restore(snapshot)?;
+        Ok(m)
+    }
 }
 
 impl Machine {
     pub fn new() -> Self {
-        let memory: Vec<u8> = vec![0; 0xffff];
-        Machine { memory: memory, stack: vec![], pc: 0, acc: 0, hlt: false, c: false, n: false }
+        let memory = Memory::new();
+        Machine { 
+            memory: memory, stack: vec![], pc: 0, acc: 0, hlt: false, c: false, n: false, 
+            stack_size: STACK_SIZE, code_size: 0, protect_code: false, detect_data_execution: false,
+            cycle_limit: None, cycles: 0, record: None, profile: None, input: Input::console(), end_of_input: EndOfInput::FAULT, output: Output::STDOUT
+        }
     }
 
 
-    pub fn load(&mut self, program: Vec<u8>) {
-        for (position, byte) in program.iter().enumerate() {
-            self.memory[position] = *byte;
+    pub fn builder() -> MachineBuilder {
+        MachineBuilder {
+            memory_size: MEMORY_SIZE, stack_size: STACK_SIZE, protect_code: false, detect_data_execution: false,
+            cycle_limit: None, record: false, profile: false, input: Input::console(), end_of_input: EndOfInput::FAULT, output: Output::STDOUT
         }
     }
 
 
-    pub fn emulate(&mut self) {
+    pub fn load(&mut self, program: Vec<u8>) -> Result<(), Fault> {
+        self.code_size = self.memory.load(&program)?;
+        Ok(())
+    }
+
+
+    pub fn emulate(&mut self) -> Result<(), Fault> {
+        if self.record.is_some() || self.profile.is_some() {
+            while !self.hlt {
+                self.step()?;
+            }
+
+            return Ok(());
+        }
+
+        let limit = self.cycle_limit.unwrap_or(u64::MAX);
         while !self.hlt {
-            self.clock_cycle();
+            if self.cycles >= limit {
+                return Err(Fault::CycleLimit(self.cycles));
+            }
+
+            self.clock_cycle()?;
+            self.cycles += 1;
         }
+
+        Ok(())
     }
 
 
-    fn clock_cycle(&mut self) {
-        let opcode: u8= self.memory[self.pc as usize];
-        let operand_u8: Vec<u8> = self.memory[(self.pc as usize + 1)..(self.pc as usize + 3)].to_vec();
-        let operand: u16 = ((operand_u8[0] as u16) << 8) | operand_u8[1] as u16;
-        self.pc += 3;
+    // Executes one instruction, nothing once halted
+    pub fn step(&mut self) -> Result<(), Fault> {
+        if self.hlt {
+            return Ok(());
+        }
+
+        if self.cycle_limit.is_some_and(|limit| self.cycles >= limit) {
+            return Err(Fault::CycleLimit(self.cycles));
+        }
+
+        let (pc, depth) = (self.pc, self.stack.len());
+        if self.record.is_some() {
+            self.recorded_cycle()?;
+        } else {
+            self.clock_cycle()?;
+        }
+
+        if let Some(profile) = &mut self.profile {
+            profile.count(pc, self.stack.len().cmp(&depth), self.pc);
+        }
+
+        self.cycles += 1;
+        Ok(())
+    }
+
+
+    pub fn snapshot(&self) -> Snapshot {
+        let mut image = self.memory.image();
+        while image.len() >= WORD_SIZE && image[image.len() - WORD_SIZE..].iter().all(|byte| *byte == 0) {
+            image.truncate(image.len() - WORD_SIZE);
+        }
+
+        Snapshot {
+            pc: self.pc, acc: self.acc, negative: self.n, carry: self.c, halted: self.hlt, cycles: self.cycles,
+            code_size: self.code_size, call_stack: self.stack.clone(), memory_size: self.memory.size(), image: image,
+        }
+    }
+
+
+    // Replaces the state of the machine with a snapshot, its settings are kept
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), Fault> {
+        if snapshot.call_stack.len() > self.stack_size {
+            return Err(Fault::StackOverflow(self.stack_size));
+        }
+
+        let mut memory = Memory::with_size(snapshot.memory_size);
+        memory.load(&snapshot.image)?;
+
+        self.memory = memory;
+        self.code_size = snapshot.code_size;
+        self.pc = snapshot.pc;
+        self.acc = snapshot.acc;
+        self.n = snapshot.negative;
+        self.c = snapshot.carry;
+        self.hlt = snapshot.halted;
+        self.cycles = snapshot.cycles;
+        self.stack = snapshot.call_stack.clone();
+        if let Some(record) = &mut self.record {
+            record.clear();
+        }
+
+        Ok(())
+    }
+
+
+    // Undoes the last recorded instruction, returning its delta, or None when there is none to undo. Input read and
+    // output written by it stay as they are.
+    pub fn step_back(&mut self) -> Option<Delta> {
+        let delta = self.record.as_mut()?.pop()?;
+        self.pc = delta.pc;
+        self.acc = delta.acc;
+        self.n = delta.negative;
+        self.c = delta.carry;
+        self.hlt = delta.halted;
+        self.cycles -= 1;
+
+        if let Some((address, value)) = delta.write {
+            // the address was written, so it is in bounds
+            self.memory.write(address, value).unwrap();
+        }
+
+        match delta.stack {
+            StackOp::NONE => {}
+            StackOp::PUSH => { self.stack.pop(); }
+            StackOp::POP(address) => { self.stack.push(address); }
+        }
+
+        Some(delta)
+    }
+
+
+    // Counts of the instructions executed, None when not profiling
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+
+    // Deltas of the instructions step_back can undo, None when not recording
+    pub fn record(&self) -> Option<&Record> {
+        self.record.as_ref()
+    }
+
+
+    pub fn halted(&self) -> bool {
+        self.hlt
+    }
+
+
+    // Negative and carry flags
+    pub fn flags(&self) -> (bool, bool) {
+        (self.n, self.c)
+    }
+
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+
+    pub fn acc(&self) -> u16 {
+        self.acc
+    }
+
+
+    // Instructions executed so far
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+
+    pub fn input(&self) -> &Input {
+        &self.input
+    }
+
+
+    pub fn input_mut(&mut self) -> &mut Input {
+        &mut self.input
+    }
+
+
+    // Output captured so far when writing to Output::BUFFER
+    pub fn output(&self) -> &str {
+        match &self.output {
+            Output::BUFFER(buffer) => { buffer }
+            Output::STDOUT => { "" }
+        }
+    }
+
+
+    // Return addresses of the active calls, outermost first
+    pub fn call_stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+
+    fn clock_cycle(&mut self) -> Result<(), Fault> {
+        let pc = self.pc;
+        let result = self.execute();
+        if result.is_err() {
+            self.pc = pc;
+        }
+
+        result
+    }
+
+
+    // A clock cycle which records its delta, a faulting instruction changes nothing so records none
+    fn recorded_cycle(&mut self) -> Result<(), Fault> {
+        let Cell { opcode, value: operand } = self.memory.fetch(self.pc)?;
+        let write = if opcode == STA { self.memory.read(operand).ok().map(|value| (operand, value)) } else { None };
+        let mut delta = Delta {
+            pc: self.pc, acc: self.acc, negative: self.n, carry: self.c, halted: self.hlt, write: write, stack: StackOp::NONE,
+        };
+
+        let (depth, top) = (self.stack.len(), self.stack.last().copied());
+        if let Err(fault) = self.execute() {
+            self.pc = delta.pc;
+            return Err(fault);
+        }
+
+        delta.stack = match self.stack.len().cmp(&depth) {
+            std::cmp::Ordering::Greater => { StackOp::PUSH }
+            std::cmp::Ordering::Less => { StackOp::POP(top.unwrap()) }
+            std::cmp::Ordering::Equal => { StackOp::NONE }
+        };
+
+        self.record.as_mut().unwrap().push(delta);
+        Ok(())
+    }
+
+
+    fn execute(&mut self) -> Result<(), Fault> {
+        let Cell { opcode, value: operand } = self.memory.fetch(self.pc)?;
+        if self.detect_data_execution && (opcode == DAT || self.pc as usize >= self.code_size) {
+            return Err(Fault::ExecuteData(self.pc));
+        }
+
+        self.pc = self.pc.wrapping_add(1);
 
         match opcode {
             0b0000 => { self.hlt = true; },  // HLT
             0b0001 => { // ADD
-                self.c = self.acc + self.address_operand(operand) > u16::MAX;
+                let (acc, carry) = u16::overflowing_add(self.acc, self.memory.read(operand)?);
+                self.c = carry;
                 self.n = false;
 
-                self.acc = u16::wrapping_add(self.acc, self.address_operand(operand));
+                self.acc = acc;
             },  
             0b0010 => { // SUB
-                self.n = self.address_operand(operand) > self.acc;
+                let value = self.memory.read(operand)?;
+                self.n = value > self.acc;
                 self.c = false;
 
-                self.acc = u16::wrapping_sub(self.acc, self.address_operand(operand));
+                self.acc = u16::wrapping_sub(self.acc, value);
                 // self.acc -= self.address_operand(operand); 
             },
-            0b0011 => { self.acc = self.address_operand(operand); },   // LDA
+            0b0011 => { self.acc = self.memory.read(operand)?; },   // LDA
             0b0100 => { // STA
-                self.memory[operand as usize + 1] = (self.acc >> 8) as u8; 
-                self.memory[operand as usize + 2] = self.acc as u8;
-            },  
+                if self.protect_code && (operand as usize) < self.code_size && self.memory.opcode(operand)? != DAT {
+                    return Err(Fault::WriteProtected(operand));
+                }
+
+                self.memory.write(operand, self.acc)?;
+            },
             0b0101 => { self.pc = operand; },  // BRA
             0b0110 => { self.pc = if self.acc == 0 { operand } else { self.pc }  },  // BRZ
             0b0111 => { self.pc = if self.acc > 0 && !self.n { operand } else { self.pc }  },  // BGT
             0b1011 => { self.pc = if self.n { operand } else { self.pc }  },  // BLT
-            0b1000 => {
-                let mut rl = rustyline::DefaultEditor::new().unwrap();
-                let line = rl.readline("").unwrap();
-
-                if !(line.trim().parse::<u16>().is_ok()) && line.trim().len() == 1 {
-                    self.acc = line.chars().nth(0).unwrap() as u16;
-                } else {
-                    self.acc = line.trim().parse::<u16>().unwrap();
-                }
-            },  // INP
-            0b1001 => { print!("{:?}", self.acc) },  // OUT
-            0b1010 => { print!("{}", char::from_u32(self.acc as u32).expect("invalid ASCII char")); },  // OTC
-            0b1100 => {},
+            0b1000 => { self.input_value(Value::NUMBER)?; },  // INP
+            0b1111 => { self.input_value(Value::CHAR)?; },  // INC
+            0b1001 => { self.output.write(self.acc) },  // OUT
+            0b1010 => {
+                let ch = char::from_u32(self.acc as u32).ok_or(Fault::InvalidCharacter(self.acc))?;
+                self.output.write(ch);
+            },  // OTC
+            DAT => {},
 
             0b1101 => { // CALL
+                if self.stack.len() >= self.stack_size {
+                    return Err(Fault::StackOverflow(self.stack_size));
+                }
+
                 self.stack.push(self.pc);
                 self.pc = operand;
             },
 
             0b1110 => { // RET
-                self.pc = self.stack.pop().unwrap();
+                self.pc = self.stack.pop().ok_or(Fault::StackUnderflow)?;
             },
-            _ => { panic!("Unexpected opcode found in emulator") }
+            _ => { return Err(Fault::InvalidOpcode(opcode)) }
         }
 
+        Ok(())
     }
 
 
-    fn address_operand(&mut self, operand: u16) -> u16 {
-        let operand_u8: Vec<u8> = self.memory[(operand as usize + 1)..(operand as usize + 3)].to_vec();
-        ((operand_u8[0] as u16) << 8) | operand_u8[1] as u16
+    fn input_value(&mut self, kind: Value) -> Result<(), Fault> {
+        // show a prompt printed before the input is read
+        self.output.flush();
+        match (self.input.read_value(kind), self.end_of_input) {
+            (Err(Fault::EndOfInput), EndOfInput::ZERO) => { self.acc = 0; }
+            (Err(Fault::EndOfInput), EndOfInput::HALT) => { self.hlt = true; }
+            (value, _) => { self.acc = value?; }
+        }
+
+        Ok(())
     }
+
 }
 
 
@@ -104,9 +456,193 @@ mod tests {
     #[test]
     fn test_clock_cycle() {
         let mut m = Machine::new();
-        m.load(vec![1, 0, 3, 0, 0, 3]);
-        m.clock_cycle();
-        assert_eq!(m.pc, 3);
+        m.load(vec![1, 0, 1, 12, 0, 3]).unwrap();
+        m.clock_cycle().unwrap();
+        assert_eq!(m.pc, 1);
         assert_eq!(m.acc, 3);
     }
+
+    #[test]
+    fn test_store_preserves_opcode() {
+        let mut m = Machine::new();
+        m.load(vec![3, 0, 3, 4, 0, 4, 0, 0, 0, 12, 0, 9, 1, 0, 0]).unwrap();
+        m.emulate().unwrap();
+        assert_eq!(m.memory.opcode(4), Ok(1));
+        assert_eq!(m.memory.read(4), Ok(9));
+        assert_eq!(m.pc, 3);
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let mut m = Machine::new();
+        m.memory = Memory::with_size(2);
+        m.load(vec![3, 0, 5, 0, 0, 0]).unwrap();
+        assert_eq!(m.emulate(), Err(Fault::MemoryOutOfBounds(5)));
+        assert_eq!(m.pc, 0);
+    }
+
+    #[test]
+    fn test_write_protection() {
+        // sta 0, sta 2, hlt (dat cell 2 remains writable)
+        let program = vec![4, 0, 3, 4, 0, 0, 0, 0, 0, 12, 0, 0];
+        let mut m = Machine::new();
+        m.load(program.clone()).unwrap();
+        m.protect_code = true;
+        assert_eq!(m.emulate(), Err(Fault::WriteProtected(0)));
+        assert_eq!(m.pc, 1);
+
+        let mut m = Machine::new();
+        m.load(program).unwrap();
+        assert_eq!(m.emulate(), Ok(()));
+    }
+
+    #[test]
+    fn test_data_execution() {
+        let mut m = Machine::new();
+        m.load(vec![12, 0, 1, 0, 0, 0]).unwrap();
+        m.detect_data_execution = true;
+        assert_eq!(m.emulate(), Err(Fault::ExecuteData(0)));
+
+        let mut m = Machine::new();
+        m.load(vec![5, 0, 1]).unwrap();
+        m.detect_data_execution = true;
+        assert_eq!(m.emulate(), Err(Fault::ExecuteData(1)));
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        // _f call _f
+        let mut m = Machine::new();
+        m.load(vec![13, 0, 0]).unwrap();
+        m.stack_size = 4;
+        assert_eq!(m.emulate(), Err(Fault::StackOverflow(4)));
+        assert_eq!(m.call_stack(), &[1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_stack_underflow() {
+        let mut m = Machine::new();
+        m.load(vec![14, 0, 0]).unwrap();
+        assert_eq!(m.emulate(), Err(Fault::StackUnderflow));
+        assert_eq!(m.pc, 0);
+    }
+
+    #[test]
+    fn test_call_stack() {
+        // call 2, hlt, call 4, ret, ret
+        let mut m = Machine::new();
+        m.load(vec![13, 0, 2, 0, 0, 0, 13, 0, 4, 14, 0, 0, 14, 0, 0]).unwrap();
+        m.clock_cycle().unwrap();
+        m.clock_cycle().unwrap();
+        assert_eq!(m.call_stack(), &[1, 3]);
+
+        m.emulate().unwrap();
+        assert!(m.call_stack().is_empty());
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut m = Machine::builder().memory_size(8).stack_size(1).load(vec![13, 0, 1, 13, 0, 1]).unwrap();
+        assert_eq!(m.emulate(), Err(Fault::StackOverflow(1)));
+
+        let mut m = Machine::builder().protect_code(true).load(vec![4, 0, 0]).unwrap();
+        assert_eq!(m.emulate(), Err(Fault::WriteProtected(0)));
+
+        let m = Machine::builder().memory_size(1).load(vec![0, 0, 0, 0, 0, 0]);
+        assert!(m.is_err());
+    }
+
+    #[test]
+    fn test_io() {
+        // inp, out, inc, otc, inp
+        let program = vec![8, 0, 0, 9, 0, 0, 15, 0, 0, 10, 0, 0, 8, 0, 0];
+        let mut m = Machine::builder().input(Input::lines("12\nA")).output(Output::BUFFER(String::new())).load(program).unwrap();
+        assert_eq!(m.emulate(), Err(Fault::EndOfInput));
+        assert_eq!(m.output(), "12A");
+        assert_eq!(m.pc, 4);
+    }
+
+    #[test]
+    fn test_end_of_input() {
+        // inp, out, hlt
+        let program = vec![8, 0, 0, 9, 0, 0, 0, 0, 0];
+        let run = |end_of_input: EndOfInput| {
+            let mut m = Machine::builder().input(Input::lines("")).end_of_input(end_of_input).output(Output::BUFFER(String::new()))
+                .load(program.clone()).unwrap();
+            let result = m.emulate();
+            (result, m.output().to_string())
+        };
+
+        assert_eq!(run(EndOfInput::FAULT), (Err(Fault::EndOfInput), String::new()));
+        assert_eq!(run(EndOfInput::ZERO), (Ok(()), String::from("0")));
+        assert_eq!(run(EndOfInput::HALT), (Ok(()), String::new()));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        // lda 6, add 6, sta 6, call 5, hlt, ret, dat 2
+        let program = vec![3, 0, 6, 1, 0, 6, 4, 0, 6, 13, 0, 5, 0, 0, 0, 14, 0, 0, 12, 0, 2];
+        let mut m = Machine::builder().memory_size(16).load(program).unwrap();
+        for _ in 0..4 {
+            m.step().unwrap();
+        }
+
+        let snapshot = Snapshot::decode(&m.snapshot().encode()).unwrap();
+        assert_eq!(snapshot.image.len(), 21);
+        assert_eq!(snapshot.call_stack, vec![4]);
+
+        let mut resumed = Machine::builder().resume(&snapshot).unwrap();
+        assert_eq!((resumed.pc(), resumed.acc(), resumed.cycles(), resumed.memory.size()), (5, 4, 4, 16));
+        resumed.emulate().unwrap();
+        m.emulate().unwrap();
+        assert_eq!(resumed.snapshot(), m.snapshot());
+        assert!(resumed.halted());
+        assert_eq!(resumed.memory.read(6), Ok(4));
+    }
+
+    #[test]
+    fn test_step_back() {
+        // lda 6, add 6, sta 6, call 5, hlt, ret, dat 2
+        let program = vec![3, 0, 6, 1, 0, 6, 4, 0, 6, 13, 0, 5, 0, 0, 0, 14, 0, 0, 12, 0, 2];
+        let mut m = Machine::builder().record(true).load(program.clone()).unwrap();
+        let start = m.snapshot();
+        let mut states = vec![];
+        while !m.halted() {
+            states.push(m.snapshot());
+            m.step().unwrap();
+        }
+
+        assert_eq!(m.record().map(|record| record.len()), Some(6));
+        assert_eq!(m.step_back().map(|delta| delta.stack), Some(StackOp::NONE));
+        assert_eq!(m.snapshot(), states[5]);
+        assert_eq!(m.step_back().map(|delta| delta.stack), Some(StackOp::POP(4)));
+        assert_eq!(m.snapshot(), states[4]);
+
+        while m.step_back().is_some() {}
+        assert_eq!(m.snapshot(), start);
+        assert_eq!(m.memory.read(6), Ok(2));
+
+        // only while recording
+        let mut m = Machine::builder().load(program).unwrap();
+        m.step().unwrap();
+        assert_eq!(m.step_back(), None);
+    }
+
+    #[test]
+    fn test_cycle_limit() {
+        // bra 0
+        let mut m = Machine::builder().cycle_limit(Some(100)).load(vec![5, 0, 0]).unwrap();
+        assert_eq!(m.emulate(), Err(Fault::CycleLimit(100)));
+        assert_eq!(m.cycles(), 100);
+
+        let mut m = Machine::builder().cycle_limit(Some(1)).load(vec![0, 0, 0]).unwrap();
+        assert_eq!(m.emulate(), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_opcode() {
+        let mut m = Machine::new();
+        m.load(vec![0xff, 0, 0]).unwrap();
+        assert_eq!(m.emulate(), Err(Fault::InvalidOpcode(0xff)));
+    }
 }
\ No newline at end of file
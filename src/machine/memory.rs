@@ -0,0 +1,132 @@
+// Memory model
+//
+// Memory is an array of word-addressed cells (mailboxes). Every cell is one 3 byte word, an 8 bit opcode followed
+// by a 16 bit big-endian operand, and an address (operand, label or pc) is the index of a cell, never a byte offset.
+//
+//     cell n = bytes [n*3, n*3+1, n*3+2] = [opcode, operand hi, operand lo]
+//
+// The value of a cell is its operand field. LDA/ADD/SUB read it, STA overwrites it and leaves the opcode untouched.
+// A DAT cell is therefore a cell holding its value in the operand field, and loading a code cell yields the operand
+// of that instruction. Binary images are the concatenation of their cells, loaded from cell 0.
+//
+// Memory may be smaller than the address space, accessing a cell beyond its end faults rather than panics. Cells are
+// held decoded, the image is only split into words when it loads.
+
+use crate::machine::fault::Fault;
+
+pub const WORD_SIZE: usize = 3;
+pub const MEMORY_SIZE: usize = 0x10000; // cells, by default every 16 bit address is valid
+
+// A cell decoded from its word, so the fetch of an instruction needs no decoding
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Cell {
+    pub opcode: u8,
+    pub value: u16,
+}
+
+pub struct Memory {
+    cells: Vec<Cell>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory::with_size(MEMORY_SIZE)
+    }
+
+    pub fn with_size(size: usize) -> Self {
+        Memory { cells: vec![Cell::default(); size] }
+    }
+
+    pub fn size(&self) -> usize {
+        self.cells.len()
+    }
+
+    // Copies an image into memory from cell 0, returns the number of cells it occupies
+    pub fn load(&mut self, image: &[u8]) -> Result<usize, Fault> {
+        let size = image.len().div_ceil(WORD_SIZE);
+        if size > self.cells.len() {
+            return Err(Fault::MemoryOutOfBounds(size));
+        }
+
+        for (cell, word) in self.cells.iter_mut().zip(image.chunks(WORD_SIZE)) {
+            // a word cut short by the end of the image leaves the rest of its cell as it was
+            let byte = |index: usize, current: u8| word.get(index).cloned().unwrap_or(current);
+            cell.opcode = byte(0, cell.opcode);
+            cell.value = ((byte(1, (cell.value >> 8) as u8) as u16) << 8) | byte(2, cell.value as u8) as u16;
+        }
+
+        Ok(size)
+    }
+
+    // The cells encoded as a binary image, the inverse of load
+    pub fn image(&self) -> Vec<u8> {
+        let mut image = Vec::with_capacity(self.cells.len() * WORD_SIZE);
+        for cell in &self.cells {
+            image.extend([cell.opcode, (cell.value >> 8) as u8, cell.value as u8]);
+        }
+
+        image
+    }
+
+    pub fn fetch(&self, address: u16) -> Result<Cell, Fault> {
+        self.cells.get(address as usize).cloned().ok_or(Fault::MemoryOutOfBounds(address as usize))
+    }
+
+    pub fn opcode(&self, address: u16) -> Result<u8, Fault> {
+        Ok(self.fetch(address)?.opcode)
+    }
+
+    pub fn read(&self, address: u16) -> Result<u16, Fault> {
+        Ok(self.fetch(address)?.value)
+    }
+
+    pub fn write(&mut self, address: u16, value: u16) -> Result<(), Fault> {
+        let cell = self.cells.get_mut(address as usize).ok_or(Fault::MemoryOutOfBounds(address as usize))?;
+        cell.value = value;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cells() {
+        let mut m = Memory::new();
+        assert_eq!(m.load(&[3, 0, 1, 12, 0, 42]), Ok(2));
+        assert_eq!(m.opcode(0), Ok(3));
+        assert_eq!(m.read(0), Ok(1));
+        assert_eq!(m.opcode(1), Ok(12));
+        assert_eq!(m.read(1), Ok(42));
+
+        m.write(1, 0x1234).unwrap();
+        assert_eq!(m.opcode(1), Ok(12));
+        assert_eq!(m.read(1), Ok(0x1234));
+        assert_eq!(m.image()[3..6], [12, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_last_cell() {
+        let mut m = Memory::new();
+        m.write(0xffff, 7).unwrap();
+        assert_eq!(m.read(0xffff), Ok(7));
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let mut m = Memory::with_size(4);
+        assert_eq!(m.read(4), Err(Fault::MemoryOutOfBounds(4)));
+        assert_eq!(m.write(0xfffe, 1), Err(Fault::MemoryOutOfBounds(0xfffe)));
+        assert_eq!(m.load(&[0; 15]), Err(Fault::MemoryOutOfBounds(5)));
+    }
+
+    #[test]
+    fn test_partial_word() {
+        let mut m = Memory::with_size(2);
+        m.write(1, 0x1234).unwrap();
+        assert_eq!(m.load(&[3, 0, 1, 12, 0]), Ok(2));
+        assert_eq!(m.fetch(1), Ok(Cell { opcode: 12, value: 0x0034 }));
+        assert_eq!(m.image(), vec![3, 0, 1, 12, 0, 0x34]);
+    }
+}
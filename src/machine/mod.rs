@@ -1 +1,8 @@
-pub mod machine;
\ No newline at end of file
+pub mod coverage;
+pub mod fault;
+pub mod io;
+pub mod machine;
+pub mod memory;
+pub mod profile;
+pub mod record;
+pub mod snapshot;
@@ -0,0 +1,216 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::assembler::disassembler::{disassemble, label};
+use crate::machine::memory::Memory;
+
+const HOT_SPOTS: usize = 10; // addresses listed in the report, the most executed
+
+// A function called from a particular chain of calls, a node of the call tree
+struct Node {
+    function: u16, // address the CALL went to, or the entry point for the root
+    parent: usize,
+    calls: u64,
+    cycles: u64, // instructions executed in the function itself, excluding its calls
+}
+
+// Instructions executed per address and per function while profiling. Functions are the targets of CALL, everything
+// before the first call is the entry point's.
+pub struct Profile {
+    counts: Vec<u64>,
+    jumps: Vec<u64>, // times the instruction at an address continued somewhere other than the address after it
+    nodes: Vec<Node>, // the root first, a node after its parent
+    children: HashMap<(usize, u16), usize>,
+    current: usize,
+}
+
+impl Profile {
+    pub fn new(entry: u16) -> Self {
+        Profile {
+            counts: vec![0; 1 << 16],
+            jumps: vec![0; 1 << 16],
+            nodes: vec![Node { function: entry, parent: 0, calls: 0, cycles: 0 }],
+            children: HashMap::new(),
+            current: 0,
+        }
+    }
+
+    // Counts the instruction executed at an address, following the call when it pushed to the call stack or the
+    // return when it popped, to the pc after it
+    pub fn count(&mut self, address: u16, stack: Ordering, pc: u16) {
+        self.counts[address as usize] += 1;
+        self.nodes[self.current].cycles += 1;
+
+        match stack {
+            Ordering::Greater => {
+                let (parent, next) = (self.current, self.nodes.len());
+                self.current = *self.children.entry((parent, pc)).or_insert(next);
+                if self.current == next {
+                    self.nodes.push(Node { function: pc, parent: parent, calls: 0, cycles: 0 });
+                }
+
+                self.nodes[self.current].calls += 1;
+            }
+            Ordering::Less => { self.current = self.nodes[self.current].parent; }
+            Ordering::Equal if pc != address.wrapping_add(1) => { self.jumps[address as usize] += 1; }
+            Ordering::Equal => {}
+        }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.nodes.iter().map(|node| node.cycles).sum()
+    }
+
+    // Times the instruction at an address was executed
+    pub fn count_at(&self, address: u16) -> u64 {
+        self.counts[address as usize]
+    }
+
+    // Times the instruction at an address jumped, for a branch the times it was taken
+    pub fn jumps_at(&self, address: u16) -> u64 {
+        self.jumps[address as usize]
+    }
+
+    // Hot spots, cycles per function and the call graph, naming addresses by the symbols
+    pub fn report(&self, memory: &Memory, symbols: &HashMap<String, u16>) -> String {
+        let total = self.cycles();
+        let percent = |cycles: u64| if total == 0 { 0.0 } else { cycles as f64 * 100.0 / total as f64 };
+        let calls: u64 = self.nodes.iter().map(|node| node.calls).sum();
+        let mut out = format!("profile: {} cycles, {} calls\n", total, calls);
+
+        let mut addresses: Vec<u16> = (0..=u16::MAX).filter(|address| self.counts[*address as usize] > 0).collect();
+        addresses.sort_by_key(|address| std::cmp::Reverse(self.counts[*address as usize]));
+
+        out += "\nhot spots\n     count       %  address\n";
+        for address in addresses.iter().take(HOT_SPOTS) {
+            let count = self.counts[*address as usize];
+            let instruction = memory.fetch(*address).map(|cell| disassemble(cell, symbols)).unwrap_or_default();
+            out += &format!(
+                "{:>10} {:>6.1}%  {:04x} {:<16} {}\n", count, percent(count), address, self.location(*address, symbols), instruction,
+            );
+        }
+
+        // cycles of a node and the calls below it
+        let mut inclusive: Vec<u64> = self.nodes.iter().map(|node| node.cycles).collect();
+        for index in (1..self.nodes.len()).rev() {
+            inclusive[self.nodes[index].parent] += inclusive[index];
+        }
+
+        let mut functions: Vec<(String, u64, u64, u64)> = vec![];
+        for (index, node) in self.nodes.iter().enumerate() {
+            let name = self.name(index, symbols);
+            let outermost = !self.ancestors(index).any(|ancestor| self.nodes[ancestor].function == node.function);
+            let total = if outermost { inclusive[index] } else { 0 };
+
+            match functions.iter_mut().find(|(n, _, _, _)| *n == name) {
+                Some(function) => {
+                    function.1 += node.calls;
+                    function.2 += node.cycles;
+                    function.3 += total;
+                }
+                None => { functions.push((name, node.calls, node.cycles, total)); }
+            }
+        }
+
+        functions.sort_by(|a, b| b.3.cmp(&a.3).then(b.2.cmp(&a.2)).then(a.0.cmp(&b.0)));
+        out += "\nfunctions\n     calls        self       %       total       %  function\n";
+        for (name, calls, cycles, total) in functions {
+            out += &format!("{:>10} {:>11} {:>6.1}% {:>11} {:>6.1}%  {}\n", calls, cycles, percent(cycles), total, percent(total), name);
+        }
+
+        out += "\ncall graph\n";
+        self.graph(0, 1, &inclusive, symbols, &mut out);
+        out
+    }
+
+    // Folded stacks, a line per chain of calls with the cycles spent in its innermost function, e.g.
+    // "entry;main;multiply 120". The input of flamegraph.pl and inferno.
+    pub fn folded(&self, symbols: &HashMap<String, u16>) -> String {
+        let mut out = String::new();
+        for (index, node) in self.nodes.iter().enumerate().filter(|(_, node)| node.cycles > 0) {
+            let mut path: Vec<String> = self.ancestors(index).map(|ancestor| self.name(ancestor, symbols)).collect();
+            path.reverse();
+            path.push(self.name(index, symbols));
+            out += &format!("{} {}\n", path.join(";"), node.cycles);
+        }
+
+        out
+    }
+
+    fn graph(&self, index: usize, depth: usize, inclusive: &[u64], symbols: &HashMap<String, u16>, out: &mut String) {
+        let node = &self.nodes[index];
+        let calls = if index == 0 { String::new() } else { format!("  calls {}", node.calls) };
+        *out += &format!("{}{}{}  cycles {}\n", "  ".repeat(depth), self.name(index, symbols), calls, inclusive[index]);
+
+        let mut children: Vec<usize> = (index + 1..self.nodes.len()).filter(|child| self.nodes[*child].parent == index).collect();
+        children.sort_by_key(|child| std::cmp::Reverse(inclusive[*child]));
+        for child in children {
+            self.graph(child, depth + 1, inclusive, symbols, out);
+        }
+    }
+
+    // Parents of a node, innermost first
+    fn ancestors(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        let first = if index == 0 { None } else { Some(self.nodes[index].parent) };
+        std::iter::successors(first, |ancestor| if *ancestor == 0 { None } else { Some(self.nodes[*ancestor].parent) })
+    }
+
+    // A function by its label, or its address when it has none
+    fn name(&self, index: usize, symbols: &HashMap<String, u16>) -> String {
+        let function = self.nodes[index].function;
+        match label(function, symbols) {
+            Some(label) => { label }
+            None if index == 0 => { String::from("entry") }
+            None => { format!("{:04x}", function) }
+        }
+    }
+
+    // An address relative to the function it is in, the nearest function at or before it, e.g. multiply+3
+    fn location(&self, address: u16, symbols: &HashMap<String, u16>) -> String {
+        let function = self.nodes.iter().enumerate().filter(|(_, node)| node.function <= address).max_by_key(|(_, node)| node.function);
+        match function {
+            Some((index, node)) if node.function == address => { self.name(index, symbols) }
+            Some((index, node)) => { format!("{}+{}", self.name(index, symbols), address - node.function) }
+            None => { String::new() }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::machine::Machine;
+
+    const SOURCE: &str = "call twice\ncall twice\nhlt\ntwice add ONE\nadd ONE\nret\nONE dat 1";
+
+    fn profiled() -> (Machine, std::collections::HashMap<String, u16>) {
+        let program = crate::assemble(SOURCE).unwrap();
+        let mut m = Machine::builder().profile(true).load(program.binary).unwrap();
+        m.emulate().unwrap();
+        (m, program.symbol_table)
+    }
+
+    #[test]
+    fn test_counts() {
+        let (m, _) = profiled();
+        let profile = m.profile().unwrap();
+        assert_eq!(profile.cycles(), 9);
+        assert_eq!((profile.count_at(0), profile.count_at(3), profile.count_at(6)), (1, 2, 0));
+        assert_eq!((profile.jumps_at(0), profile.jumps_at(3)), (0, 0));
+    }
+
+    #[test]
+    fn test_report() {
+        let (m, symbols) = profiled();
+        let report = m.profile().unwrap().report(&m.memory, &symbols);
+        assert!(report.starts_with("profile: 9 cycles, 2 calls\n"));
+        assert!(report.contains("         2   22.2%  0003 twice            add ONE\n"));
+        assert!(report.contains("         2           6   66.7%           6   66.7%  twice\n"));
+        assert!(report.ends_with("call graph\n  entry  cycles 9\n    twice  calls 2  cycles 6\n"));
+    }
+
+    #[test]
+    fn test_folded() {
+        let (m, symbols) = profiled();
+        assert_eq!(m.profile().unwrap().folded(&symbols), "entry 3\nentry;twice 6\n");
+    }
+}
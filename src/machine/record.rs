@@ -0,0 +1,87 @@
+use std::collections::VecDeque;
+
+pub const RECORD_LIMIT: usize = 1_000_000; // deltas kept, older ones are dropped first
+
+// How an instruction changed the call stack
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StackOp {
+    NONE,
+    PUSH,     // CALL pushed a return address
+    POP(u16), // RET popped this return address
+}
+
+// The state an instruction changed, as it was before the instruction. Undoing the deltas from the latest back
+// restores an earlier state of the machine, apart from input read and output written.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Delta {
+    pub pc: u16,
+    pub acc: u16,
+    pub negative: bool,
+    pub carry: bool,
+    pub halted: bool,
+    pub write: Option<(u16, u16)>, // address written by STA and its previous value
+    pub stack: StackOp,
+}
+
+// The deltas of the instructions executed while recording, oldest first
+pub struct Record {
+    deltas: VecDeque<Delta>,
+    limit: usize,
+}
+
+impl Record {
+    pub fn new(limit: usize) -> Self {
+        Record { deltas: VecDeque::new(), limit: limit }
+    }
+
+    pub fn push(&mut self, delta: Delta) {
+        if self.deltas.len() >= self.limit {
+            self.deltas.pop_front();
+        }
+
+        self.deltas.push_back(delta);
+    }
+
+    pub fn pop(&mut self) -> Option<Delta> {
+        self.deltas.pop_back()
+    }
+
+    // Oldest first, reverse it to search back from the latest
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Delta> {
+        self.deltas.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(pc: u16) -> Delta {
+        Delta { pc: pc, acc: 0, negative: false, carry: false, halted: false, write: None, stack: StackOp::NONE }
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut record = Record::new(2);
+        for pc in 0..3 {
+            record.push(delta(pc));
+        }
+
+        assert_eq!(record.len(), 2);
+        assert_eq!(record.pop(), Some(delta(2)));
+        assert_eq!(record.pop(), Some(delta(1)));
+        assert_eq!(record.pop(), None);
+    }
+}
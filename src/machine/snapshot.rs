@@ -0,0 +1,138 @@
+use crate::error::{Error, Result};
+use crate::machine::memory::MEMORY_SIZE;
+use crate::reader::Reader;
+
+// Snapshot file format
+//
+// A snapshot is the state of a machine at some cycle, so a run can be saved and resumed later, e.g. to reproduce a
+// fault. Settings such as the stack size or code protection are not part of it, they are given again on resume. All
+// integers are big-endian.
+//
+//     magic "LMCS", version u8
+//     pc u16, acc u16, flags u8 (bit 0 negative, bit 1 carry, bit 2 halted)
+//     cycles u64
+//     code size u32 (cells of the loaded program)
+//     call stack depth u16, per call: return address u16 (outermost first)
+//     memory size u32 (cells, at most the address space), image length u32, image (trailing empty cells left out)
+
+const MAGIC: &[u8] = b"LMCS";
+const VERSION: u8 = 1;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Snapshot {
+    pub pc: u16,
+    pub acc: u16,
+    pub negative: bool,
+    pub carry: bool,
+    pub halted: bool,
+    pub cycles: u64,
+    pub code_size: usize,
+    pub call_stack: Vec<u16>,
+    pub memory_size: usize,
+    pub image: Vec<u8>,
+}
+
+impl Snapshot {
+    pub fn is_snapshot(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out: Vec<u8> = MAGIC.to_vec();
+        out.push(VERSION);
+
+        out.extend(self.pc.to_be_bytes());
+        out.extend(self.acc.to_be_bytes());
+        out.push(self.negative as u8 | (self.carry as u8) << 1 | (self.halted as u8) << 2);
+        out.extend(self.cycles.to_be_bytes());
+        out.extend((self.code_size as u32).to_be_bytes());
+
+        out.extend((self.call_stack.len() as u16).to_be_bytes());
+        for address in &self.call_stack {
+            out.extend(address.to_be_bytes());
+        }
+
+        out.extend((self.memory_size as u32).to_be_bytes());
+        out.extend((self.image.len() as u32).to_be_bytes());
+        out.extend(&self.image);
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Snapshot> {
+        let mut r = Reader::new(bytes, "snapshot", Error::Snapshot);
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err(Error::Snapshot(String::from("not a snapshot, missing LMCS header")));
+        }
+
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(Error::Snapshot(format!("unsupported snapshot version, got: {}", version)));
+        }
+
+        let pc = r.u16()?;
+        let acc = r.u16()?;
+        let flags = r.u8()?;
+        let cycles = r.u64()?;
+        let code_size = r.u32()? as usize;
+
+        let mut call_stack: Vec<u16> = vec![];
+        for _ in 0..r.u16()? {
+            call_stack.push(r.u16()?);
+        }
+
+        // the memory is allocated on resume, a size beyond the address space is never valid
+        let memory_size = r.u32()? as usize;
+        if memory_size > MEMORY_SIZE {
+            return Err(Error::Snapshot(format!("memory size exceeds {} cells, got: {}", MEMORY_SIZE, memory_size)));
+        }
+
+        let length = r.u32()? as usize;
+        let image = r.take(length)?.to_vec();
+        r.end()?;
+
+        Ok(Snapshot {
+            pc: pc, acc: acc, negative: flags & 1 != 0, carry: flags & 2 != 0, halted: flags & 4 != 0,
+            cycles: cycles, code_size: code_size, call_stack: call_stack, memory_size: memory_size, image: image,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            pc: 4, acc: 0xffff, negative: true, carry: false, halted: true, cycles: 1 << 40, code_size: 3,
+            call_stack: vec![1, 7], memory_size: 100, image: vec![3, 0, 2, 13, 0, 0, 12, 0, 1],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = snapshot().encode();
+        assert!(Snapshot::is_snapshot(&bytes));
+        assert_eq!(&bytes[..5], b"LMCS\x01");
+        assert_eq!(Snapshot::decode(&bytes), Ok(snapshot()));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(Snapshot::decode(b"LMCO\x01"), Err(Error::Snapshot(String::from("not a snapshot, missing LMCS header"))));
+        assert_eq!(Snapshot::decode(b"LMCS\x02"), Err(Error::Snapshot(String::from("unsupported snapshot version, got: 2"))));
+
+        let bytes = snapshot().encode();
+        assert!(Snapshot::decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_decode_memory_size() {
+        let mut too_large = snapshot();
+        too_large.memory_size = u32::MAX as usize;
+        assert_eq!(Snapshot::decode(&too_large.encode()), Err(Error::Snapshot(String::from("memory size exceeds 65536 cells, got: 4294967295"))));
+
+        let mut largest = snapshot();
+        largest.memory_size = MEMORY_SIZE;
+        assert_eq!(Snapshot::decode(&largest.encode()), Ok(largest));
+    }
+}
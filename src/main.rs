@@ -1,7 +1,8 @@
+#![allow(clippy::upper_case_acronyms)]
+
+use std::io::{IsTerminal, Read, Write};
 use clap::Parser as ClapParser;
-mod machine;
-mod compiler;
-mod assembler;
+use lmc::machine;
 
 #[derive(ClapParser)]
 struct Cli {
@@ -10,97 +11,683 @@ struct Cli {
 }
 
 
+#[derive(clap::Args)]
+struct MachineArgs {
+    /// Number of memory cells, accesses beyond it fault
+    #[arg(long, default_value_t = machine::memory::MEMORY_SIZE,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(0..=machine::memory::MEMORY_SIZE as u64))]
+    memory: usize,
+
+    /// Fault on writes to instruction cells of the loaded program
+    #[arg(long)]
+    protect_code: bool,
+
+    /// Fault on execution of DAT cells or beyond the loaded program
+    #[arg(long)]
+    detect_data_execution: bool,
+
+    /// Maximum call depth, deeper calls fault with a stack overflow
+    #[arg(long, default_value_t = machine::machine::STACK_SIZE)]
+    stack_size: usize,
+
+    /// Fault after this many instructions without halting
+    #[arg(long)]
+    cycle_limit: Option<u64>,
+
+    /// Read the input for INP from a file, one value per line
+    #[arg(long, conflicts_with = "input_string")]
+    input: Option<std::path::PathBuf>,
+
+    /// Input for INP as values separated by commas, e.g. "5,+,3"
+    #[arg(long)]
+    input_string: Option<String>,
+
+    /// What INP does once the input is exhausted
+    #[arg(long, value_enum, default_value = "fault")]
+    on_eof: OnEof,
+
+    /// Save a snapshot of the machine to this file when it faults, resume it with emulate --resume
+    #[arg(long)]
+    snapshot_on_fault: Option<std::path::PathBuf>,
+
+    /// Exit with the accumulator at HLT as the status (capped at 239), compiled programs halt with the entry point's return value
+    #[arg(long)]
+    exit_code: bool,
+}
+
+
+#[derive(clap::Args)]
+struct CompilerArgs {
+    /// Optimization level, 0 emits the generated assembly and 1 runs the peephole optimizer
+    #[arg(short = 'O', default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=1))]
+    opt_level: u8,
+
+    /// Function the program starts in, main or _main by default
+    #[arg(long)]
+    entry: Option<String>,
+}
+
+impl CompilerArgs {
+    fn options(&self) -> lmc::Options {
+        lmc::Options { opt_level: self.opt_level, entry: self.entry.clone() }
+    }
+}
+
+
+// Reports on a run, printed to stderr once the machine halts or faults
+#[derive(clap::Args)]
+struct ReportArgs {
+    /// Count the instructions executed per address and function, printing a report to stderr
+    #[arg(long)]
+    profile: bool,
+
+    /// Also write the profile as folded stacks to this file, for flamegraph.pl or inferno
+    #[arg(long, requires = "profile")]
+    profile_folded: Option<std::path::PathBuf>,
+
+    /// Print the .lmc or .lmasc source to stderr with the times each line and branch was executed
+    #[arg(long)]
+    coverage: bool,
+
+    /// Also write the coverage as an lcov tracefile to this file, for genhtml
+    #[arg(long, requires = "coverage")]
+    coverage_lcov: Option<std::path::PathBuf>,
+}
+
+impl ReportArgs {
+    fn any(&self) -> bool {
+        self.profile || self.coverage
+    }
+}
+
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum OnEof {
+    FAULT,
+    ZERO,
+    HALT,
+}
+
+impl OnEof {
+    fn policy(&self) -> machine::io::EndOfInput {
+        match self {
+            OnEof::FAULT => { machine::io::EndOfInput::FAULT }
+            OnEof::ZERO => { machine::io::EndOfInput::ZERO }
+            OnEof::HALT => { machine::io::EndOfInput::HALT }
+        }
+    }
+}
+
+
+// Kind of an input file, detected from its extension unless given with --kind
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+enum Kind {
+    LMC,
+    LMASC,
+    OBJ,
+    BIN,
+}
+
+impl Kind {
+    fn detect(path: &std::path::Path) -> Option<Kind> {
+        match path.extension()?.to_str()? {
+            "lmc" => { Some(Kind::LMC) }
+            "lmasc" => { Some(Kind::LMASC) }
+            "obj" => { Some(Kind::OBJ) }
+            "bin" => { Some(Kind::BIN) }
+            _ => { None }
+        }
+    }
+
+    // First stage of the pipeline this kind of input can produce
+    fn stage(&self) -> Emit {
+        match self {
+            Kind::LMC => { Emit::TOKENS }
+            Kind::LMASC => { Emit::ASM }
+            Kind::OBJ => { Emit::OBJ }
+            Kind::BIN => { Emit::BIN }
+        }
+    }
+}
+
+// Stages of the pipeline, in order
+#[derive(Clone, Copy, PartialEq, PartialOrd, clap::ValueEnum)]
+enum Emit {
+    TOKENS,
+    AST,
+    #[value(name = "ast-json")]
+    ASTJSON,
+    IR,
+    ASM,
+    OBJ,
+    BIN,
+}
+
+
+// Paths given as - read from stdin or write to stdout, outputs which are omitted are written to stdout
 #[derive(ClapParser)]
 enum Subcommand {
     Assemble {
         path: std::path::PathBuf,
-        out: std::path::PathBuf,
+        out: Option<std::path::PathBuf>,
+
+        #[arg(long)]
+        listing: Option<std::path::PathBuf>,
     }, 
 
     Emulate {
-        path: std::path::PathBuf,
+        #[arg(required_unless_present = "resume")]
+        path: Option<std::path::PathBuf>,
+
+        /// Continue from a snapshot instead of loading a binary
+        #[arg(long, conflicts_with = "path")]
+        resume: Option<std::path::PathBuf>,
+
+        #[command(flatten)]
+        machine: MachineArgs,
     },
 
     Compile {
         path: std::path::PathBuf,
-        out: std::path::PathBuf,
+        out: Option<std::path::PathBuf>,
+
+        #[command(flatten)]
+        compiler: CompilerArgs,
+    },
+
+    // Runs the pipeline from the kind of the input up to --emit
+    Build {
+        path: std::path::PathBuf,
+        out: Option<std::path::PathBuf>,
+
+        #[arg(long, value_enum, default_value = "bin")]
+        emit: Emit,
+
+        /// Kind of the file, detected from its extension by default
+        #[arg(long, value_enum)]
+        kind: Option<Kind>,
+
+        #[command(flatten)]
+        compiler: CompilerArgs,
     },
 
     Semicompile {
         path: std::path::PathBuf,
+
+        #[command(flatten)]
+        compiler: CompilerArgs,
+
+        #[command(flatten)]
+        machine: MachineArgs,
     },
 
+    // Builds and emulates a .lmc, .lmasc, .obj or .bin file, other files are assembled
     Run {
         path: std::path::PathBuf,
+
+        /// Kind of the file, detected from its extension by default
+        #[arg(long, value_enum)]
+        kind: Option<Kind>,
+
+        #[command(flatten)]
+        reports: ReportArgs,
+
+        #[command(flatten)]
+        compiler: CompilerArgs,
+
+        #[command(flatten)]
+        machine: MachineArgs,
+    },
+
+    // Builds a .lmc, .lmasc, .obj or .bin file and loads it into the debugger
+    Debug {
+        path: std::path::PathBuf,
+
+        /// Kind of the file, detected from its extension by default
+        #[arg(long, value_enum)]
+        kind: Option<Kind>,
+
+        #[command(flatten)]
+        compiler: CompilerArgs,
+
+        #[command(flatten)]
+        machine: MachineArgs,
+    },
+
+    // Builds a .lmc, .lmasc, .obj or .bin file and shows it running in a full screen view of the machine
+    Tui {
+        path: std::path::PathBuf,
+
+        /// Kind of the file, detected from its extension by default
+        #[arg(long, value_enum)]
+        kind: Option<Kind>,
+
+        #[command(flatten)]
+        compiler: CompilerArgs,
+
+        #[command(flatten)]
+        machine: MachineArgs,
+    },
+
+    // Runs the golden-file cases and #[test] functions in the given files and directories, golden-file cases check
+    // their output against .out files
+    Test {
+        #[arg(default_value = "examples")]
+        paths: Vec<std::path::PathBuf>,
+
+        /// Regenerate the .out files from the actual output instead of checking it
+        #[arg(long)]
+        bless: bool,
+    },
+}
+
+// Input for INP, given as a file or string, piped on stdin, or else prompted for on the terminal
+fn machine_input(args: &MachineArgs) -> machine::io::Input {
+    if let Some(path) = &args.input {
+        return machine::io::Input::lines(&read_to_string(path));
+    }
+
+    if let Some(values) = &args.input_string {
+        return machine::io::Input::values(values);
+    }
+
+    if std::io::stdin().is_terminal() {
+        machine::io::Input::console()
+    } else {
+        machine::io::Input::STDIN
     }
 }
 
-fn emulate(program: Vec<u8>) {
-    let mut m = machine::machine::Machine::new();
-    m.load(program);
-    m.emulate();
+fn machine_builder(args: &MachineArgs) -> lmc::MachineBuilder {
+    lmc::Machine::builder()
+        .input(machine_input(args))
+        .end_of_input(args.on_eof.policy())
+        .memory_size(args.memory)
+        .stack_size(args.stack_size)
+        .protect_code(args.protect_code)
+        .detect_data_execution(args.detect_data_execution)
+        .cycle_limit(args.cycle_limit)
 }
 
-fn assemble(path: std::path::PathBuf) -> Vec<u8> {
-    let content = std::fs::read_to_string(path).expect("could not read file");
+fn load(builder: lmc::MachineBuilder, program: Vec<u8>) -> lmc::Machine {
+    builder.load(program).unwrap_or_else(|fault| exit_with_status(format!("machine fault: {}", fault), fault.exit_code()))
+}
 
-    let mut l = assembler::lexer::Lexer::new(content.chars().collect());
-    let tokens: Vec<assembler::lexer::Token> = l.lex();
+fn emulate(program: Vec<u8>, args: MachineArgs) {
+    execute(load(machine_builder(&args), program), args);
+}
 
-    let mut p = assembler::parser::Parser::new(tokens);
-    let (program, symbol_table) = p.parse();
+fn resume(path: &std::path::Path, args: MachineArgs) {
+    let snapshot = lmc::machine::snapshot::Snapshot::decode(&read(path)).unwrap_or_else(|e| exit_with(e));
+    let m = machine_builder(&args).resume(&snapshot)
+        .unwrap_or_else(|fault| exit_with_status(format!("machine fault: {}", fault), fault.exit_code()));
+    execute(m, args);
+}
 
-    let mut c = assembler::assembler::Compiler::new(program, symbol_table);
-    c.compile()
+fn execute(m: lmc::Machine, args: MachineArgs) {
+    execute_then(m, args, |_| {});
 }
 
-fn compile(program: String) -> String {
-    let mut l = compiler::lexer::Lexer::new(program.chars().collect());
-    let tokens = l.lex();
+// Runs the machine, then calls after before exiting, whether it halted or faulted
+fn execute_then(mut m: lmc::Machine, args: MachineArgs, after: impl FnOnce(&lmc::Machine)) {
+    if let Err(fault) = m.emulate() {
+        std::io::stdout().flush().unwrap();
+        eprintln!("\nmachine fault: {}, pc: {:04x}", fault, m.pc());
+        eprint!("{}", backtrace(m.call_stack()));
+
+        if let Some(path) = &args.snapshot_on_fault {
+            write(Some(path), &m.snapshot().encode());
+            eprintln!("snapshot saved to {}", path.display());
+        }
+
+        after(&m);
+        std::process::exit(fault.exit_code());
+    }
 
-    let mut p = compiler::parser::Parser::new(tokens);
-    let ast = p.parse();
+    after(&m);
+
+    if args.exit_code {
+        std::io::stdout().flush().unwrap();
+        std::process::exit(machine::fault::halt_status(m.acc()));
+    }
+}
+
+fn exit_with(error: impl std::fmt::Display) -> ! {
+    exit_with_status(error, lmc::error::ERROR_STATUS)
+}
+
+fn exit_with_status(error: impl std::fmt::Display, status: i32) -> ! {
+    eprintln!("{}", error);
+    std::process::exit(status);
+}
+
+fn is_stdio(path: &std::path::Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+fn read(path: &std::path::Path) -> Vec<u8> {
+    let mut content: Vec<u8> = vec![];
+    let result = if is_stdio(path) {
+        std::io::stdin().read_to_end(&mut content).map(|_| content)
+    } else {
+        std::fs::read(path)
+    };
+
+    result.unwrap_or_else(|e| exit_with(format!("could not read {}: {}", path.display(), e)))
+}
+
+fn read_to_string(path: &std::path::Path) -> String {
+    utf8(read(path), path)
+}
+
+fn write(path: Option<&std::path::Path>, content: &[u8]) {
+    let result = match path {
+        Some(path) if !is_stdio(path) => { std::fs::write(path, content) }
+        _ => { std::io::stdout().write_all(content).and_then(|_| std::io::stdout().flush()) }
+    };
+
+    result.unwrap_or_else(|e| exit_with(format!("could not write output: {}", e)))
+}
+
+fn backtrace(call_stack: &[u16]) -> String {
+    let mut out = String::from("call stack:\n");
+    if call_stack.is_empty() {
+        out += "  <empty>\n";
+    }
+
+    // Innermost call first, each frame is the address of its CALL instruction
+    for (depth, return_address) in call_stack.iter().rev().enumerate() {
+        out += &format!("  #{depth} call at {:04x}\n", return_address.wrapping_sub(1));
+    }
 
-    let mut c = compiler::compiler::Compiler::new();
-    let out = c.compile(ast);
     out
 }
 
+fn utf8(content: Vec<u8>, path: &std::path::Path) -> String {
+    String::from_utf8(content).unwrap_or_else(|_| exit_with(format!("{} is not valid UTF-8", path.display())))
+}
+
+fn build(path: &std::path::Path, kind: Kind, emit: Emit, compiler: &CompilerArgs) -> Vec<u8> {
+    if emit < kind.stage() {
+        exit_with(format!("cannot emit {} from {}", name(emit), name(kind)));
+    }
+
+    let content = read(path);
+    let object = match kind {
+        Kind::LMC => {
+            let source = utf8(content, path);
+            match emit {
+                Emit::TOKENS => {
+                    let tokens = lmc::tokens(&source).unwrap_or_else(|e| exit_with(e));
+                    return tokens.iter().map(|token| format!("{:<12}{}\n", token.kind(), token)).collect::<String>().into_bytes();
+                }
+                Emit::AST => { return lmc::parse(&source).unwrap_or_else(|e| exit_with(e)).tree().into_bytes(); }
+                Emit::ASTJSON => { return (lmc::parse(&source).unwrap_or_else(|e| exit_with(e)).to_json() + "\n").into_bytes(); }
+                Emit::IR => { return lmc::lower(&source, &compiler.options()).unwrap_or_else(|e| exit_with(e)).to_string().into_bytes(); }
+                Emit::ASM => { return compile(source, compiler).into_bytes(); }
+                _ => {}
+            }
+
+            assemble(&compile(source, compiler), None).object()
+        }
+        Kind::LMASC => {
+            if emit == Emit::ASM {
+                return content;
+            }
+
+            assemble(&utf8(content, path), None).object()
+        }
+        Kind::OBJ => { lmc::Object::decode(&content).unwrap_or_else(|e| exit_with(e)) }
+        Kind::BIN => { return content; }
+    };
+
+    match emit {
+        Emit::OBJ => { object.encode() }
+        _ => { object.binary }
+    }
+}
+
+// Builds a file into an object, the symbols come with it and a bare binary has none
+fn object(path: &std::path::Path, kind: Kind, compiler: &CompilerArgs) -> lmc::Object {
+    match kind {
+        Kind::BIN => { lmc::Object::new(read(path), std::collections::HashMap::new(), vec![]) }
+        _ => { lmc::Object::decode(&build(path, kind, Emit::OBJ, compiler)).unwrap_or_else(|e| exit_with(e)) }
+    }
+}
+
+// Builds a .lmc or .lmasc file into an object along with its source and the source line of each address, .lmc
+// lines come from the compiler's debug info
+fn object_with_lines(path: &std::path::Path, kind: Kind, compiler: &CompilerArgs) -> (lmc::Object, String, Vec<Option<usize>>) {
+    let source = read_to_string(path);
+    match kind {
+        Kind::LMC => {
+            let (asm, lines) = lmc::compile_with_lines(&source, &compiler.options()).unwrap_or_else(|e| exit_with(e));
+            (assemble(&asm, None).object(), source, lines)
+        }
+        Kind::LMASC => {
+            let program = assemble(&source, None);
+            let lines = program.lines.iter().map(|line| Some(*line)).collect();
+            (program.object(), source, lines)
+        }
+        _ => { exit_with(format!("cannot report coverage of {}, it needs a .lmc or .lmasc source", name(kind))) }
+    }
+}
+
+// Runs a file profiled, then prints the reports asked for
+fn report(path: &std::path::Path, kind: Kind, compiler: &CompilerArgs, args: MachineArgs, reports: ReportArgs) {
+    let (object, source, lines) = match reports.coverage {
+        true => { object_with_lines(path, kind, compiler) }
+        false => { (object(path, kind, compiler), String::new(), vec![]) }
+    };
+
+    let m = load(machine_builder(&args).profile(true), object.binary);
+    execute_then(m, args, |m| {
+        let profile = m.profile().unwrap();
+        std::io::stdout().flush().unwrap();
+
+        if reports.profile {
+            eprint!("\n{}", profile.report(&m.memory, &object.symbol_table));
+        }
+
+        if let Some(folded) = &reports.profile_folded {
+            write(Some(folded), profile.folded(&object.symbol_table).as_bytes());
+        }
+
+        if reports.coverage {
+            let coverage = lmc::machine::coverage::Coverage::new(profile, &m.memory, &lines);
+            let name = path.display().to_string();
+            eprint!("\n{}", coverage.text(&name, &source));
+
+            if let Some(lcov) = &reports.coverage_lcov {
+                write(Some(lcov), coverage.lcov(&name).as_bytes());
+            }
+        }
+    });
+}
+
+fn debug(path: &std::path::Path, kind: Kind, compiler: &CompilerArgs, args: MachineArgs) {
+    use lmc::debugger::debugger::{Debugger, Reply};
+
+    let object = object(path, kind, compiler);
+    let m = load(machine_builder(&args).record(true), object.binary);
+    let mut debugger = Debugger::new(m, object.symbol_table);
+    let mut rl = rustyline::DefaultEditor::new().unwrap_or_else(|e| exit_with(format!("could not start the debugger: {}", e)));
+    println!("{}", debugger.current());
+
+    while let Ok(line) = rl.readline("(lmc) ") {
+        rl.add_history_entry(line.as_str()).ok();
+        let reply = debugger.command(&line);
+        std::io::stdout().flush().unwrap();
+
+        match reply {
+            Reply::OUTPUT(output) if output.is_empty() => {}
+            Reply::OUTPUT(output) => { println!("{}", output); }
+            Reply::QUIT => { break; }
+        }
+    }
+}
+
+fn tui(path: &std::path::Path, kind: Kind, compiler: &CompilerArgs, args: MachineArgs) {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        exit_with("lmc tui needs a terminal");
+    }
+
+    let program = build(path, kind, Emit::BIN, compiler);
+
+    // without input given up front the view asks for each value
+    let prompt = args.input.is_none() && args.input_string.is_none();
+    let input = if prompt { machine::io::Input::lines("") } else { machine_input(&args) };
+
+    let builder = machine_builder(&args).input(input).output(machine::io::Output::BUFFER(String::new())).record(true);
+    let view = lmc::tui::tui::Tui::new(load(builder, program), prompt);
+    view.run().unwrap_or_else(|e| exit_with(format!("terminal error: {}", e)));
+}
+
+fn test(paths: &[std::path::PathBuf], bless: bool) {
+    use lmc::harness::{golden, unit};
+
+    let cases = golden::discover(paths).unwrap_or_else(|e| exit_with(format!("could not find test cases: {}", e)));
+    let (mut passed, mut failed) = (0, 0);
+    let mut report = |name: &str, result: Result<&str, String>| {
+        match result {
+            Ok(status) => {
+                println!("{name} ... {status}");
+                passed += 1;
+            }
+            Err(detail) => {
+                println!("{name} ... {detail}");
+                failed += 1;
+            }
+        }
+    };
+
+    for case in &cases {
+        let name = case.source.display().to_string();
+        let tests = match unit::tests_in(&case.source) {
+            Ok(tests) => { tests }
+            Err(e) => {
+                report(&name, Err(format!("ERROR\n{e}")));
+                continue;
+            }
+        };
+
+        if !bless && !tests.is_empty() {
+            let source = read_to_string(&case.source);
+            for test in &tests {
+                let result = match unit::run(&source, test, &lmc::Options::new()) {
+                    Ok(unit::Outcome::PASSED) => { Ok("ok") }
+                    Ok(unit::Outcome::FAILED(message)) => { Err(format!("FAILED\n{}\n", message.trim_end())) }
+                    Err(e) => { Err(format!("ERROR\n{e}\n")) }
+                };
+
+                report(&format!("{name}::{test}"), result);
+            }
+        }
+
+        // a file of unit tests only has no expected output
+        if !tests.is_empty() && !case.expected.exists() {
+            continue;
+        }
+
+        if bless {
+            report(&name, case.bless().map(|_| "blessed").map_err(|e| format!("ERROR\n{e}")));
+            continue;
+        }
+
+        let result = match case.check() {
+            golden::Outcome::PASSED => { Ok("ok") }
+            golden::Outcome::FAILED(diff) => { Err(format!("FAILED\n{diff}")) }
+            golden::Outcome::MISSING => { Err(format!("FAILED, no {}, run with --bless to create it", case.expected.display())) }
+            golden::Outcome::ERROR(e) => { Err(format!("ERROR\n{e}")) }
+        };
+
+        report(&name, result);
+    }
+
+    println!("\n{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn name(value: impl clap::ValueEnum) -> String {
+    value.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default()
+}
+
+fn assemble(content: &str, listing: Option<std::path::PathBuf>) -> lmc::Program {
+    let program = lmc::assemble(content).unwrap_or_else(|e| exit_with(e));
+
+    if let Some(listing) = listing {
+        let out = program.listing(content).unwrap_or_else(|e| exit_with(e));
+        write(Some(&listing), out.as_bytes());
+    }
+
+    program
+}
+
+fn compile(program: String, args: &CompilerArgs) -> String {
+    lmc::compile_with(&program, &args.options()).unwrap_or_else(|e| exit_with(e))
+}
+
 fn main () {
-    let args = Cli::parse();    
+    // usage errors exit with the toolchain's status like any other error, --help still exits with 0
+    let args = Cli::try_parse().unwrap_or_else(|e| {
+        e.print().ok();
+        std::process::exit(if e.exit_code() == 0 { 0 } else { lmc::error::ERROR_STATUS });
+    });    
     match args.subcommand {
-        Subcommand::Assemble { path, out } => {
-            let bin: Vec<u8> = assemble(path);
-            std::fs::write(out, bin).unwrap();
+        Subcommand::Assemble { path, out, listing } => {
+            let program = assemble(&read_to_string(&path), listing);
+            write(out.as_deref(), &program.binary);
+        }
+
+        Subcommand::Emulate { path, resume: Some(snapshot), machine } => {
+            debug_assert!(path.is_none());
+            resume(&snapshot, machine);
+        }
+
+        Subcommand::Emulate { path, resume: None, machine } => {
+            let program = read(&path.unwrap());
+            emulate(program, machine);
+        }
+
+        Subcommand::Run { path, kind, reports, compiler, machine } if reports.any() => {
+            let kind = kind.or(Kind::detect(&path)).unwrap_or(Kind::LMASC);
+            report(&path, kind, &compiler, machine, reports);
         }
 
-        Subcommand::Emulate { path } => {
-            let program = std::fs::read(path).expect("could not read from file");
-            emulate(program);
+        Subcommand::Run { path, kind, compiler, machine, .. } => {
+            let kind = kind.or(Kind::detect(&path)).unwrap_or(Kind::LMASC);
+            emulate(build(&path, kind, Emit::BIN, &compiler), machine);
         }
 
-        Subcommand::Run { path } => {
-            let program: Vec<u8> = assemble(path);
-            emulate(program);
+        Subcommand::Compile { path, out, compiler } => {
+            let content = read_to_string(&path);
+            write(out.as_deref(), compile(content, &compiler).as_bytes());
         }
 
-        Subcommand::Compile { path, out } => {
-            let content = std::fs::read_to_string(path).expect("could not read file ");
-            std::fs::write(out, compile(content)).unwrap();
+        Subcommand::Build { path, out, emit, kind, compiler } => {
+            let kind = kind.or(Kind::detect(&path))
+                .unwrap_or_else(|| exit_with(format!("cannot detect the kind of {}, give it with --kind", path.display())));
+            write(out.as_deref(), &build(&path, kind, emit, &compiler));
         }
 
-        Subcommand::Semicompile { path } => {
-            let content = std::fs::read_to_string(path).expect("could not read file ");
-            let mut l = assembler::lexer::Lexer::new(compile(content).chars().collect());
-            let tokens: Vec<assembler::lexer::Token> = l.lex();
+        Subcommand::Semicompile { path, compiler, machine } => {
+            emulate(build(&path, Kind::LMC, Emit::BIN, &compiler), machine);
+        }
 
-            let mut p = assembler::parser::Parser::new(tokens);
-            let (program, symbol_table) = p.parse();
+        Subcommand::Debug { path, kind, compiler, machine } => {
+            let kind = kind.or(Kind::detect(&path)).unwrap_or(Kind::LMASC);
+            debug(&path, kind, &compiler, machine);
+        }
 
-            let mut c = assembler::assembler::Compiler::new(program, symbol_table);
-            let program: Vec<u8> = c.compile();
+        Subcommand::Tui { path, kind, compiler, machine } => {
+            let kind = kind.or(Kind::detect(&path)).unwrap_or(Kind::LMASC);
+            tui(&path, kind, &compiler, machine);
+        }
 
-            emulate(program);
+        Subcommand::Test { paths, bless } => {
+            test(&paths, bless);
         }
     }
 }
\ No newline at end of file
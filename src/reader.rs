@@ -0,0 +1,68 @@
+use crate::error::{Error, Result};
+
+// Reads the big-endian integers and byte strings of a binary format such as an object file or a snapshot, errors
+// are reported as the format's own with the offset they occurred at
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    format: &'static str,      // name of the format in messages, e.g. "object"
+    error: fn(String) -> Error, // the format's error, e.g. Error::Object
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8], format: &'static str, error: fn(String) -> Error) -> Self {
+        Reader { bytes: bytes, position: 0, format: format, error: error }
+    }
+
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() - self.position < n {
+            return Err((self.error)(format!("unexpected end of {}, at: {}", self.format, self.position)));
+        }
+
+        let slice = &self.bytes[self.position..self.position + n];
+        self.position += n;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    // Checks every byte was read
+    pub fn end(&self) -> Result<()> {
+        if self.position != self.bytes.len() {
+            return Err((self.error)(format!("trailing bytes after {}, at: {}", self.format, self.position)));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let mut r = Reader::new(&[1, 0, 2, 0, 0, 0, 3, 9], "test", Error::Object);
+        assert_eq!((r.u8(), r.u16(), r.u32()), (Ok(1), Ok(2), Ok(3)));
+        assert_eq!(r.end(), Err(Error::Object(String::from("trailing bytes after test, at: 7"))));
+        assert_eq!(r.u16(), Err(Error::Object(String::from("unexpected end of test, at: 7"))));
+        assert_eq!(r.take(1), Ok(&[9][..]));
+        assert_eq!(r.end(), Ok(()));
+    }
+}
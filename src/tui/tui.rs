@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor};
+use crossterm::{cursor, execute, queue, terminal};
+
+use crate::assembler::disassembler::disassemble;
+use crate::machine::io::{parse_input, Value};
+use crate::machine::machine::Machine;
+
+const SPEEDS: [u32; 9] = [1, 2, 5, 10, 20, 50, 100, 1000, 10000]; // instructions per second
+const SPEED: usize = 2; // starting speed, an index into SPEEDS
+const FRAME: Duration = Duration::from_millis(20); // shortest time between redraws
+const CELL_WIDTH: usize = 16; // e.g. "0004 call 65535 "
+const OUTPUT_LINES: usize = 3; // lines of the output tape shown, the latest ones
+const FIXED_LINES: usize = 9 + OUTPUT_LINES; // lines around the memory grid
+const KEYS: &str = "space play/pause  s step  b back  + - speed  q quit";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Style {
+    PLAIN,
+    TITLE,
+    PC,      // the cell about to execute
+    WRITTEN, // the cell last written by STA
+}
+
+pub type Line = Vec<(String, Style)>;
+
+enum Mode {
+    PAUSED,
+    PLAYING,
+    INPUT(String, Value, bool), // typing the value INP or INC reads, and whether to play on once entered
+}
+
+// Full screen view of a machine running a program, stepped by hand or played at a chosen speed
+pub struct Tui {
+    machine: Machine,
+    prompt: bool, // ask for a value when INP or INC finds no queued input, otherwise the end-of-input policy applies
+    mode: Mode,
+    speed: usize,
+    status: String,
+}
+
+impl Tui {
+    // The machine should record, for stepping back and the last written cell, and write its output to a buffer
+    pub fn new(machine: Machine, prompt: bool) -> Self {
+        Tui { machine: machine, prompt: prompt, mode: Mode::PAUSED, speed: SPEED, status: String::new() }
+    }
+
+    pub fn run(mut self) -> std::io::Result<()> {
+        let mut out = std::io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+        let result = self.event_loop(&mut out);
+
+        execute!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+        terminal::disable_raw_mode()?;
+        result
+    }
+
+    fn event_loop(&mut self, out: &mut impl Write) -> std::io::Result<()> {
+        let mut next = Instant::now();
+        loop {
+            let (width, height) = terminal::size()?;
+            self.draw(out, width as usize, height as usize)?;
+
+            let timeout = match self.mode {
+                Mode::PLAYING => { next.saturating_duration_since(Instant::now()) }
+                _ => { Duration::from_secs(60) }
+            };
+
+            if event::poll(timeout)? {
+                // anything else, e.g. a resize, only redraws
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press && !self.key(key) {
+                        return Ok(());
+                    }
+                }
+
+                continue;
+            }
+
+            if let Mode::PLAYING = self.mode {
+                // faster than a frame, run a frame's worth of instructions between redraws
+                let interval = Duration::from_secs(1) / SPEEDS[self.speed];
+                let steps = (FRAME.as_nanos() / interval.as_nanos()).max(1);
+                for _ in 0..steps {
+                    if !self.step() {
+                        break;
+                    }
+                }
+
+                next = Instant::now() + interval.max(FRAME);
+            }
+        }
+    }
+
+    // Handles a key press, returning false to quit
+    fn key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+
+        if let Mode::INPUT(text, kind, play) = &mut self.mode {
+            match key.code {
+                KeyCode::Char(ch) => { text.push(ch); }
+                KeyCode::Backspace => { text.pop(); }
+                KeyCode::Esc => { self.mode = Mode::PAUSED; }
+                KeyCode::Enter => {
+                    match parse_input(text, *kind) {
+                        Ok(_) => {
+                            self.machine.input_mut().push(text);
+                            self.mode = if *play { Mode::PLAYING } else { Mode::PAUSED };
+                            self.status.clear();
+                            self.step();
+                        }
+                        Err(fault) => { self.status = format!("{}, {}", fault, kind); }
+                    }
+                }
+                _ => {}
+            }
+
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => { return false; }
+            KeyCode::Char(' ') => {
+                self.mode = match self.mode {
+                    Mode::PLAYING => { Mode::PAUSED }
+                    _ => { Mode::PLAYING }
+                };
+            }
+            KeyCode::Char('s') | KeyCode::Right => {
+                self.mode = Mode::PAUSED;
+                self.step();
+            }
+            KeyCode::Char('b') | KeyCode::Left => {
+                self.mode = Mode::PAUSED;
+                self.status = match self.machine.step_back() {
+                    Some(_) => { String::new() }
+                    None => { String::from("at the start of the recording") }
+                };
+            }
+            KeyCode::Char('+') | KeyCode::Char('=') | KeyCode::Up => { self.speed = (self.speed + 1).min(SPEEDS.len() - 1); }
+            KeyCode::Char('-') | KeyCode::Down => { self.speed = self.speed.saturating_sub(1); }
+            _ => {}
+        }
+
+        true
+    }
+
+    // Executes one instruction, returning false and pausing when it halts, faults or waits for input
+    fn step(&mut self) -> bool {
+        if self.machine.halted() {
+            self.mode = Mode::PAUSED;
+            return false;
+        }
+
+        if let Some(kind) = self.reads_input() {
+            if self.prompt && self.machine.input().queued().is_none_or(|lines| lines.is_empty()) {
+                let play = matches!(self.mode, Mode::PLAYING);
+                self.mode = Mode::INPUT(String::new(), kind, play);
+                return false;
+            }
+        }
+
+        match self.machine.step() {
+            Ok(()) if self.machine.halted() => {
+                self.mode = Mode::PAUSED;
+                false
+            }
+            Ok(()) => { true }
+            Err(fault) => {
+                self.status = format!("machine fault: {}", fault);
+                self.mode = Mode::PAUSED;
+                false
+            }
+        }
+    }
+
+    // The kind of value the instruction about to execute reads, if it is INP or INC
+    fn reads_input(&self) -> Option<Value> {
+        match self.machine.memory.fetch(self.machine.pc()).map(|cell| cell.opcode) {
+            Ok(0b1000) => { Some(Value::NUMBER) }
+            Ok(0b1111) => { Some(Value::CHAR) }
+            _ => { None }
+        }
+    }
+
+    fn draw(&self, out: &mut impl Write, width: usize, height: usize) -> std::io::Result<()> {
+        for (row, line) in self.render(width, height).iter().enumerate() {
+            queue!(out, cursor::MoveTo(0, row as u16))?;
+
+            let mut left = width;
+            for (text, style) in line {
+                let text: String = text.chars().take(left).collect();
+                left -= text.chars().count();
+
+                match style {
+                    Style::PLAIN => {}
+                    Style::TITLE => { queue!(out, SetAttribute(Attribute::Bold))?; }
+                    Style::PC => { queue!(out, SetAttribute(Attribute::Reverse))?; }
+                    Style::WRITTEN => { queue!(out, SetForegroundColor(Color::Yellow), SetAttribute(Attribute::Bold))?; }
+                }
+
+                queue!(out, Print(text), SetAttribute(Attribute::Reset), ResetColor)?;
+            }
+
+            queue!(out, terminal::Clear(terminal::ClearType::UntilNewLine))?;
+        }
+
+        queue!(out, terminal::Clear(terminal::ClearType::FromCursorDown))?;
+        out.flush()
+    }
+
+    // The screen as lines of styled text, the memory grid taking the rows left by the rest
+    fn render(&self, width: usize, height: usize) -> Vec<Line> {
+        let m = &self.machine;
+        let state = match self.mode {
+            _ if m.halted() => { "halted" }
+            Mode::PLAYING => { "playing" }
+            Mode::PAUSED => { "paused" }
+            Mode::INPUT(..) => { "waiting for input" }
+        };
+
+        let mut lines: Vec<Line> = vec![];
+        let title = format!("LMC  {}  speed {}/s  cycles {}", state, SPEEDS[self.speed], m.cycles());
+        lines.push(vec![(title, Style::TITLE)]);
+        lines.push(vec![]);
+
+        lines.extend(self.grid(width, height.saturating_sub(FIXED_LINES).max(1)));
+        lines.push(vec![]);
+
+        let (negative, carry) = m.flags();
+        lines.push(plain(format!("acc {}  pc {:04x}  n {}  c {}", m.acc(), m.pc(), negative as u8, carry as u8)));
+
+        let stack: Vec<String> = m.call_stack().iter().map(|address| format!("{:04x}", address)).collect();
+        lines.push(plain(format!("call stack: {}", if stack.is_empty() { String::from("<empty>") } else { stack.join(" ") })));
+
+        let input = match m.input().queued() {
+            Some(queued) if queued.is_empty() => { String::from("<empty>") }
+            Some(queued) => { queued.iter().map(|line| line.as_str()).collect::<Vec<&str>>().join(", ") }
+            None => { String::from("<stdin>") }
+        };
+        lines.push(plain(format!("input: {}", input)));
+
+        lines.push(plain(String::from("output:")));
+        let output: Vec<&str> = m.output().lines().collect();
+        let start = output.len().saturating_sub(OUTPUT_LINES);
+        for row in 0..OUTPUT_LINES {
+            lines.push(plain(format!("  {}", output.get(start + row).unwrap_or(&""))));
+        }
+
+        lines.push(match &self.mode {
+            Mode::INPUT(text, kind, _) if self.status.is_empty() => { plain(format!("input, {}: {}_", kind, text)) }
+            Mode::INPUT(text, _, _) => { plain(format!("{}: {}_", self.status, text)) }
+            _ => { plain(self.status.clone()) }
+        });
+        lines.push(plain(String::from(KEYS)));
+
+        lines
+    }
+
+    // Rows of cells, scrolled to keep the pc in view
+    fn grid(&self, width: usize, rows: usize) -> Vec<Line> {
+        let m = &self.machine;
+        let columns = (width / CELL_WIDTH).max(1);
+        let total = m.memory.size().div_ceil(columns);
+
+        let first = (m.pc() as usize / columns).saturating_sub(rows / 2).min(total.saturating_sub(rows));
+        let symbols = HashMap::new(); // the view has no labels, addresses are shown as numbers
+        let written = m.record().and_then(|record| record.iter().rev().find_map(|delta| delta.write)).map(|(address, _)| address);
+
+        let mut lines: Vec<Line> = vec![];
+        for row in first..(first + rows).min(total) {
+            let mut line: Line = vec![];
+            for address in row * columns..((row + 1) * columns).min(m.memory.size()) {
+                let address = address as u16;
+                let style = match address {
+                    _ if address == m.pc() => { Style::PC }
+                    _ if Some(address) == written => { Style::WRITTEN }
+                    _ => { Style::PLAIN }
+                };
+
+                let cell = m.memory.fetch(address).map(|cell| disassemble(cell, &symbols)).unwrap_or_else(|_| String::from("??"));
+                line.push((format!("{:04x} {:<10.10}", address, cell), style));
+                line.push((String::from(" "), Style::PLAIN));
+            }
+
+            lines.push(line);
+        }
+
+        lines
+    }
+}
+
+fn plain(text: String) -> Line {
+    vec![(text, Style::PLAIN)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::io::{Input, Output};
+
+    fn tui(input: &str) -> Tui {
+        let program = crate::assemble("inp\nsta X\nout\nhlt\nX dat 1").unwrap();
+        let machine = Machine::builder()
+            .memory_size(8)
+            .record(true)
+            .input(Input::lines(input))
+            .output(Output::BUFFER(String::new()))
+            .load(program.binary)
+            .unwrap();
+
+        Tui::new(machine, true)
+    }
+
+    fn press(tui: &mut Tui, keys: &str) {
+        for ch in keys.chars() {
+            let code = if ch == '\n' { KeyCode::Enter } else { KeyCode::Char(ch) };
+            assert!(tui.key(KeyEvent::new(code, KeyModifiers::NONE)));
+        }
+    }
+
+    fn text(line: &Line) -> String {
+        line.iter().map(|(text, _)| text.as_str()).collect::<String>().trim_end().to_string()
+    }
+
+    #[test]
+    fn test_render() {
+        let mut t = tui("7");
+        press(&mut t, "ss");
+
+        let lines = t.render(34, 16);
+        assert_eq!(lines.len(), 16);
+        assert_eq!(text(&lines[0]), "LMC  paused  speed 5/s  cycles 2");
+        assert_eq!(text(&lines[2]), "0000 inp        0001 sta 4");
+        assert_eq!(text(&lines[3]), "0002 out        0003 hlt");
+        assert_eq!(text(&lines[4]), "0004 dat 7      0005 hlt");
+        assert_eq!(lines[3][0].1, Style::PC);
+        assert_eq!(lines[4][0].1, Style::WRITTEN);
+        assert_eq!(text(&lines[7]), "acc 7  pc 0002  n 0  c 0");
+        assert_eq!(text(&lines[9]), "input: <empty>");
+
+        // the written cell stays marked after instructions which write nothing
+        press(&mut t, "s");
+        let lines = t.render(34, 16);
+        assert_eq!(text(&lines[11]), "  7");
+        assert_eq!(lines[4][0].1, Style::WRITTEN);
+    }
+
+    #[test]
+    fn test_input() {
+        let mut t = tui("");
+        press(&mut t, "s");
+        assert!(matches!(t.mode, Mode::INPUT(..)));
+        assert_eq!(text(&t.render(34, 16)[14]), "input, expected a number from 0 to 65535: _");
+
+        press(&mut t, "x\n");
+        assert_eq!(text(&t.render(34, 16)[14]), "invalid input, got: \"x\", expected a number from 0 to 65535: x_");
+
+        t.key(KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE));
+        press(&mut t, "42\n");
+        assert_eq!((t.machine.acc(), t.machine.cycles()), (42, 1));
+        assert!(matches!(t.mode, Mode::PAUSED));
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut t = tui("3");
+        press(&mut t, "+++");
+        assert_eq!(SPEEDS[t.speed], 50);
+        press(&mut t, "-");
+        assert_eq!(SPEEDS[t.speed], 20);
+
+        press(&mut t, "b");
+        assert_eq!(t.status, "at the start of the recording");
+        press(&mut t, "sssss");
+        assert!(t.machine.halted());
+        press(&mut t, "bb");
+        assert_eq!(t.machine.pc(), 2);
+        assert!(!t.key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE)));
+    }
+}
@@ -0,0 +1,70 @@
+use std::path::PathBuf;
+
+use lmc::harness::golden::{self, Outcome};
+use lmc::harness::unit;
+
+fn examples() -> Vec<golden::Case> {
+    let examples = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples");
+    golden::discover(&[examples]).unwrap()
+}
+
+// Every example must produce its golden output, regenerate them with `lmc test --bless`
+#[test]
+fn test_examples() {
+    let cases = examples();
+    assert!(!cases.is_empty());
+
+    let mut failures: Vec<String> = vec![];
+    for case in &cases {
+        // a file of unit tests only has no expected output
+        if !unit::tests_in(&case.source).unwrap().is_empty() && !case.expected.exists() {
+            continue;
+        }
+
+        match case.check() {
+            Outcome::PASSED => {}
+            Outcome::FAILED(diff) => { failures.push(format!("{}:\n{}", case.source.display(), diff)); }
+            Outcome::MISSING => { failures.push(format!("{}: no {}", case.source.display(), case.expected.display())); }
+            Outcome::ERROR(e) => { failures.push(format!("{}: {}", case.source.display(), e)); }
+        }
+    }
+
+    assert!(failures.is_empty(), "{} of {} examples failed\n{}", failures.len(), cases.len(), failures.join("\n"));
+}
+
+// Every #[test] function in the examples must pass
+#[test]
+fn test_unit_tests() {
+    let mut failures: Vec<String> = vec![];
+    let mut count = 0;
+    for case in examples() {
+        let source = std::fs::read_to_string(&case.source).unwrap();
+        for test in unit::tests_in(&case.source).unwrap() {
+            count += 1;
+            match unit::run(&source, &test, &lmc::Options::new()) {
+                Ok(unit::Outcome::PASSED) => {}
+                Ok(unit::Outcome::FAILED(message)) => { failures.push(format!("{}::{}: {}", case.source.display(), test, message)); }
+                Err(e) => { failures.push(format!("{}::{}: {}", case.source.display(), test, e)); }
+            }
+        }
+    }
+
+    assert!(count > 0);
+    assert!(failures.is_empty(), "{} of {} tests failed\n{}", failures.len(), count, failures.join("\n"));
+}
+
+// The prebuilt binaries must be what their assembly assembles to
+#[test]
+fn test_binaries() {
+    let examples = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let mut count = 0;
+    for entry in std::fs::read_dir(examples.join("out")).unwrap() {
+        let binary = entry.unwrap().path();
+        let source = examples.join("asm").join(binary.file_stem().unwrap()).with_extension("lmasc");
+        let program = lmc::assemble(&std::fs::read_to_string(&source).unwrap()).unwrap();
+        assert_eq!(program.binary, std::fs::read(&binary).unwrap(), "{} is stale, assemble {}", binary.display(), source.display());
+        count += 1;
+    }
+
+    assert!(count > 0);
+}